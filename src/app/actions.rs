@@ -1,9 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{self, Display};
 use std::slice::Iter;
+use std::str::FromStr;
+use serde::Deserialize;
 use crate::inputs::key::Key;
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub enum Action {
     Quit,
     MoveRight,
@@ -12,12 +14,20 @@ pub enum Action {
     MoveDown,
     TabRight,
     TabLeft,
+    Reload,
+    Presets,
+    Profiles,
+    Sync,
+    Link,
+    Undo,
+    Redo,
+    History,
     Scale(u8),
 }
 
 impl Action {
     pub fn iterator() -> Iter<'static, Action> {
-        static ACTIONS: [Action; 17] = [
+        static ACTIONS: [Action; 25] = [
             Action::Quit,
             Action::MoveRight,
             Action::MoveLeft,
@@ -25,6 +35,14 @@ impl Action {
             Action::MoveDown,
             Action::TabRight,
             Action::TabLeft,
+            Action::Reload,
+            Action::Presets,
+            Action::Profiles,
+            Action::Sync,
+            Action::Link,
+            Action::Undo,
+            Action::Redo,
+            Action::History,
             Action::Scale(1),
             Action::Scale(2),
             Action::Scale(3),
@@ -39,7 +57,8 @@ impl Action {
         ACTIONS.iter()
     }
 
-    pub fn keys(&self) -> Vec<Key> {
+    ///Keys the action has unless the config says otherwise
+    pub fn default_keys(&self) -> Vec<Key> {
         match self {
             Action::Quit => vec![Key::Char('q')],
             Action::MoveRight => vec![Key::Char('l'), Key::Right],
@@ -48,7 +67,15 @@ impl Action {
             Action::MoveDown => vec![Key::Char('j'), Key::Down],
             Action::TabRight => vec![Key::Char('L'), Key::Char('.'), Key::Char('>')],
             Action::TabLeft => vec![Key::Char('H'), Key::Char(','), Key::Char('<')],
-            Action::Scale(n) => vec![Key::Char(('0' as u8 + n) as char)],
+            Action::Reload => vec![Key::Char('r')],
+            Action::Presets => vec![Key::Char('t')],
+            Action::Profiles => vec![Key::Char('p')],
+            Action::Sync => vec![Key::Char('s')],
+            Action::Link => vec![Key::Char('S')],
+            Action::Undo => vec![Key::Char('u')],
+            Action::Redo => vec![Key::Ctrl('r')],
+            Action::History => vec![Key::Char('U')],
+            Action::Scale(n) => vec![Key::Char((b'0' + n) as char)],
         }
     }
 }
@@ -63,40 +90,74 @@ impl Display for Action {
             Action::MoveDown => write!(f, "MoveDown"),
             Action::TabRight => write!(f, "TabRight"),
             Action::TabLeft => write!(f, "TabLeft"),
+            Action::Reload => write!(f, "Reload"),
+            Action::Presets => write!(f, "Presets"),
+            Action::Profiles => write!(f, "Profiles"),
+            Action::Sync => write!(f, "Sync"),
+            Action::Link => write!(f, "Link"),
+            Action::Undo => write!(f, "Undo"),
+            Action::Redo => write!(f, "Redo"),
+            Action::History => write!(f, "History"),
             Action::Scale(n) => write!(f, "Scale{}", n),
         }
     }
 }
 
-#[derive(Default, Debug, Clone)]
-pub struct Actions(Vec<Action>);
+impl FromStr for Action {
+    type Err = String;
 
-impl Actions {
-    pub fn find(&self, key: Key) -> Option<&Action> {
+    ///Parse the name Display gives an action, ignoring case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
         Action::iterator()
-            .filter(|action| self.0.contains(action))
-            .find(|action| action.keys().contains(&key))
+            .find(|action| action.to_string().eq_ignore_ascii_case(s.trim()))
+            .copied()
+            .ok_or_else(|| format!("unknown action '{}'", s))
     }
+}
 
-    pub fn actions(&self) -> &[Action] {
-        self.0.as_slice()
+///The keys of every action. In the config it's a table of key to action name:
+///`"<Ctrl+l>" = "TabRight"`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "BTreeMap<String, String>")]
+pub struct KeyMap(HashMap<Action, Vec<Key>>);
+
+impl TryFrom<BTreeMap<String, String>> for KeyMap {
+    type Error = String;
+
+    fn try_from(table: BTreeMap<String, String>) -> Result<Self, Self::Error> {
+        let bindings = table
+            .iter()
+            .map(|(key, action)| Ok((key.parse::<Key>()?, action.parse::<Action>()?)))
+            .collect::<Result<Vec<_>, String>>()?;
+        KeyMap::with_bindings(&bindings)
     }
 }
 
-impl From<Vec<Action>> for Actions {
-    fn from(actions: Vec<Action>) -> Self {
-        let mut map: HashMap<Key, Vec<Action>> = HashMap::new();
-        for action in actions.iter() {
-            for key in action.keys().iter() {
-                match map.get_mut(key) {
-                    Some(vec) => vec.push(*action),
-                    None => {
-                        map.insert(*key, vec![*action]);
-                    }
-                }
+impl Default for KeyMap {
+    fn default() -> Self {
+        KeyMap(Action::iterator().map(|a| (*a, a.default_keys())).collect())
+    }
+}
+
+impl KeyMap {
+    ///The default keys, except for actions that have bindings. Those get only the keys bound to
+    ///them. Two actions on one key is an error
+    pub fn with_bindings(bindings: &[(Key, Action)]) -> Result<KeyMap, String> {
+        let mut map = KeyMap::default();
+        for (_, action) in bindings {
+            map.0.insert(*action, vec![]);
+        }
+        for (key, action) in bindings {
+            map.0.entry(*action).or_default().push(*key);
+        }
+
+        let mut owners: HashMap<Key, Vec<Action>> = HashMap::new();
+        for action in Action::iterator() {
+            for key in map.keys(*action) {
+                owners.entry(*key).or_default().push(*action);
             }
         }
-        let errors = map
+        let mut errors = owners
             .iter()
             .filter(|(_, actions)| actions.len() > 1)
             .map(|(key, actions)| {
@@ -105,12 +166,49 @@ impl From<Vec<Action>> for Actions {
                     .map(Action::to_string)
                     .collect::<Vec<_>>()
                     .join(", ");
-                format!("Conflict key {} with actions {}", key, actions)
+                format!("{} is bound to {}", key, actions)
             })
             .collect::<Vec<_>>();
-        if !errors.is_empty() {
-            panic!("{}", errors.join("; "))
+        errors.sort();
+        match errors.is_empty() {
+            true => Ok(map),
+            false => Err(format!("conflicting keys: {}", errors.join("; "))),
         }
-        Self(actions)
+    }
+
+    pub fn keys(&self, action: Action) -> &[Key] {
+        self.0.get(&action).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
+
+///The actions that are available right now, with their keys
+#[derive(Default, Debug, Clone)]
+pub struct Actions {
+    actions: Vec<Action>,
+    keymap: KeyMap,
+}
+
+impl Actions {
+    pub fn new(actions: Vec<Action>, keymap: KeyMap) -> Self {
+        Actions { actions, keymap }
+    }
+
+    pub fn find(&self, key: Key) -> Option<&Action> {
+        self.actions
+            .iter()
+            .find(|action| self.keymap.keys(**action).contains(&key))
+    }
+
+    pub fn actions(&self) -> &[Action] {
+        self.actions.as_slice()
+    }
+
+    ///Keys of an action
+    pub fn keys(&self, action: Action) -> &[Key] {
+        self.keymap.keys(action)
+    }
+
+    pub fn keymap(&self) -> &KeyMap {
+        &self.keymap
     }
 }
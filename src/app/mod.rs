@@ -1,9 +1,13 @@
 use log::{debug, error, warn};
-use self::{actions::Actions, state::AppState};
+use self::{actions::{Actions, KeyMap}, state::{AppState, Tab}};
 use crate::app::actions::Action;
 use crate::inputs::key::Key;
 use crate::io::IoEvent;
-use crate::disp_mgr::DispMgr;
+use crate::config::{Config, Preset};
+use crate::disp_mgr::group::Group;
+use crate::disp_mgr::history::History;
+use crate::disp_mgr::disp::Disp;
+use crate::night_light::{self, NightLight, Status, Target};
 
 pub mod actions;
 pub mod state;
@@ -19,13 +23,46 @@ pub struct App {
     io_tx: std::sync::mpsc::Sender<IoEvent>,
     actions: Actions,
     is_loading: bool,
+    ///Last error from the IO thread, shown in the status line
+    status: Option<String>,
+    ///Color temperature presets, from the config
+    presets: Vec<Preset>,
+    ///Selected row of the preset menu. None when the menu is closed
+    preset_menu: Option<usize>,
+    ///Names of the saved profiles. The IO thread has the profiles themselves
+    profiles: Vec<String>,
+    ///Selected row of the profile menu. The row after the last profile is for saving a new one
+    profile_menu: Option<usize>,
+    ///Name being typed for a new profile
+    profile_name: Option<String>,
+    ///Changes that can be undone and redone, as of the last IoEvent. The IO thread has the real one
+    history: History,
+    ///Whether the history view is open
+    history_menu: bool,
+    night_light: Option<NightLight>,
+    ///Where the night light is at, as of the last tick
+    night_light_status: Option<Status>,
+    ///What the night light last sent to the IO thread, so it's only sent when it changes
+    night_light_target: Option<Target>,
+    ///Whether the night light sets the displays. Not when a daemon follows the schedule already
+    drives_night_light: bool,
+    ///Whether changes go to every display. Mirrors the IO thread, which does the actual linking
+    linked: bool,
+    groups: Vec<Group>,
+    ///Group the IO thread was last told about
+    group: Option<usize>,
     pub state: AppState,
 }
 
 impl App {
     ///Create new App. Needs io_tx for dispatching commands to IO thread
     pub fn new(io_tx: std::sync::mpsc::Sender<IoEvent>) -> Self {
-        let actions = vec![Action::Quit].into();
+        App::with_config(io_tx, &Config::default())
+    }
+
+    ///Create new App with settings from the config
+    pub fn with_config(io_tx: std::sync::mpsc::Sender<IoEvent>, config: &Config) -> Self {
+        let actions = Actions::new(vec![Action::Quit], config.keys.clone());
         let is_loading = false;
         let state = AppState::default();
 
@@ -33,13 +70,39 @@ impl App {
             io_tx,
             actions,
             is_loading,
+            status: None,
+            presets: config.presets(),
+            preset_menu: None,
+            profiles: Vec::new(),
+            profile_menu: None,
+            profile_name: None,
+            history: History::default(),
+            history_menu: false,
+            night_light: config.night_light.clone().map(NightLight::new),
+            night_light_status: None,
+            night_light_target: None,
+            drives_night_light: true,
+            linked: false,
+            groups: config.groups.clone(),
+            group: None,
             state,
         }
     }
 
     ///Does something in the UI. Depending on action, dispatch IO event to IO thread
     pub fn do_action(&mut self, key: Key) -> AppReturn {
-        if let Some(action) = self.actions.find(key) {
+        if self.preset_menu.is_some() {
+            self.do_preset_action(key);
+            return AppReturn::Continue;
+        }
+        if self.profile_menu.is_some() {
+            self.do_profile_action(key);
+            return AppReturn::Continue;
+        }
+        if self.history_menu && self.do_history_action(key) {
+            return AppReturn::Continue;
+        }
+        if let Some(action) = self.actions.find(key).copied() {
             debug!("Run action [{:?}]", action);
             match action {
                 Action::Quit => AppReturn::Exit,
@@ -59,9 +122,46 @@ impl App {
                     self.state.tab_left();
                     AppReturn::Continue
                 }
+                Action::Reload => {
+                    self.dispatch(IoEvent::Reload);
+                    AppReturn::Continue
+                }
+                Action::Sync => {
+                    if let Some(device_index) = self.device_index() {
+                        self.dispatch(IoEvent::Sync(device_index));
+                    }
+                    AppReturn::Continue
+                }
+                Action::Link => {
+                    self.linked = !self.linked;
+                    self.dispatch(IoEvent::Link(self.linked));
+                    AppReturn::Continue
+                }
+                Action::Profiles => {
+                    self.profile_menu = Some(0);
+                    AppReturn::Continue
+                }
+                Action::Undo => {
+                    self.dispatch(IoEvent::Undo);
+                    AppReturn::Continue
+                }
+                Action::Redo => {
+                    self.dispatch(IoEvent::Redo);
+                    AppReturn::Continue
+                }
+                Action::History => {
+                    self.history_menu = true;
+                    AppReturn::Continue
+                }
+                Action::Presets => {
+                    if !self.presets.is_empty() {
+                        self.preset_menu = Some(0);
+                    }
+                    AppReturn::Continue
+                }
                 Action::MoveRight => {
                     self.state.move_right();
-                    if let Some(device_index) = self.state.tab_index() {
+                    if let Some(device_index) = self.device_index() {
                         if let Some(focused_prop) = self.state.focused_prop() {
                             self.dispatch(IoEvent::DeviceIncrement(device_index, focused_prop));
                         }
@@ -70,7 +170,7 @@ impl App {
                 }
                 Action::MoveLeft => {
                     self.state.move_left();
-                    if let Some(device_index) = self.state.tab_index() {
+                    if let Some(device_index) = self.device_index() {
                         if let Some(focused_prop) = self.state.focused_prop() {
                             self.dispatch(IoEvent::DeviceDecrement(device_index, focused_prop));
                         }
@@ -78,10 +178,10 @@ impl App {
                     AppReturn::Continue
                 }
                 Action::Scale(s) => {
-                    self.state.scale(*s);
-                    if let Some(device_index) = self.state.tab_index() {
+                    self.state.scale(s);
+                    if let Some(device_index) = self.device_index() {
                         if let Some(focused_prop) = self.state.focused_prop() {
-                            self.dispatch(IoEvent::DeviceScale(device_index, focused_prop, *s));
+                            self.dispatch(IoEvent::DeviceScale(device_index, focused_prop, s));
                         }
                     }
                     AppReturn::Continue
@@ -93,11 +193,203 @@ impl App {
         }
     }
 
+    ///Handle a key while the preset menu is open. Up and down pick a preset, enter applies it to the
+    ///focused display and 'a' to all of them. Anything that would close a menu closes it
+    fn do_preset_action(&mut self, key: Key) {
+        let selected = match self.preset_menu {
+            Some(selected) => selected,
+            None => return,
+        };
+        let kelvin = self.presets[selected].kelvin;
+        match (key, self.actions.find(key)) {
+            (_, Some(Action::MoveUp)) => {
+                self.preset_menu = Some((selected + self.presets.len() - 1) % self.presets.len());
+            }
+            (_, Some(Action::MoveDown)) => {
+                self.preset_menu = Some((selected + 1) % self.presets.len());
+            }
+            (Key::Enter, _) => {
+                self.preset_menu = None;
+                if let Some(device_index) = self.device_index() {
+                    self.dispatch(IoEvent::SetTemperature(Some(device_index), kelvin));
+                }
+            }
+            (Key::Char('a'), _) => {
+                self.preset_menu = None;
+                self.dispatch(IoEvent::SetTemperature(None, kelvin));
+            }
+            (Key::Esc, _) | (_, Some(Action::Presets)) | (_, Some(Action::Quit)) => self.preset_menu = None,
+            _ => {}
+        }
+    }
+
+    ///Handle a key while the profile menu is open. Enter applies the selected profile, or on the last
+    ///row starts typing the name of a new one. 1-9 apply a profile right away and 'w' saves over the
+    ///selected one
+    fn do_profile_action(&mut self, key: Key) {
+        let selected = match self.profile_menu {
+            Some(selected) => selected,
+            None => return,
+        };
+        if let Some(name) = self.profile_name.as_mut() {
+            match key {
+                Key::Char(c) => name.push(c),
+                Key::Backspace => {
+                    name.pop();
+                }
+                Key::Enter if !name.trim().is_empty() => {
+                    let name = name.trim().to_string();
+                    self.close_profile_menu();
+                    self.dispatch(IoEvent::SaveProfile(name));
+                }
+                Key::Esc => self.profile_name = None,
+                _ => {}
+            }
+            return;
+        }
+
+        let rows = self.profiles.len() + 1;
+        let selected_profile = self.profiles.get(selected).cloned();
+        match (key, self.actions.find(key)) {
+            (_, Some(Action::MoveUp)) => self.profile_menu = Some((selected + rows - 1) % rows),
+            (_, Some(Action::MoveDown)) => self.profile_menu = Some((selected + 1) % rows),
+            (Key::Enter, _) => match selected_profile {
+                Some(name) => {
+                    self.close_profile_menu();
+                    self.dispatch(IoEvent::ApplyProfile(name));
+                }
+                None => self.profile_name = Some(String::new()),
+            },
+            (Key::Char(c @ '1'..='9'), _) => {
+                if let Some(name) = self.profiles.get(c as usize - '1' as usize).cloned() {
+                    self.close_profile_menu();
+                    self.dispatch(IoEvent::ApplyProfile(name));
+                }
+            }
+            (Key::Char('w'), _) => {
+                if let Some(name) = selected_profile {
+                    self.close_profile_menu();
+                    self.dispatch(IoEvent::SaveProfile(name));
+                }
+            }
+            (Key::Esc, _) | (_, Some(Action::Profiles)) | (_, Some(Action::Quit)) => self.close_profile_menu(),
+            _ => {}
+        }
+    }
+
+    ///Handle a key while the history view is open. Undo and redo still work so the view can be
+    ///watched while stepping through it, anything else goes to the view. Returns whether the key
+    ///was used up
+    fn do_history_action(&mut self, key: Key) -> bool {
+        match (key, self.actions.find(key)) {
+            (_, Some(Action::Undo)) | (_, Some(Action::Redo)) => false,
+            (Key::Esc, _) | (_, Some(Action::History)) | (_, Some(Action::Quit)) => {
+                self.history_menu = false;
+                true
+            }
+            _ => true,
+        }
+    }
+
+    ///Set the undo and redo history. Set by the io thread
+    pub fn set_history(&mut self, history: History) {
+        self.history = history;
+    }
+
+    ///Return the undo and redo history
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    ///Return whether the history view is open
+    pub fn history_menu(&self) -> bool {
+        self.history_menu
+    }
+
+    fn close_profile_menu(&mut self) {
+        self.profile_menu = None;
+        self.profile_name = None;
+    }
+
+    ///Set the names of the saved profiles. Set by the io thread
+    pub fn set_profiles(&mut self, profiles: Vec<String>) {
+        self.profiles = profiles;
+    }
+
+    ///Return the names of the saved profiles
+    pub fn profiles(&self) -> &[String] {
+        &self.profiles
+    }
+
+    ///Return the selected row of the profile menu, or None if it is closed
+    pub fn profile_menu(&self) -> Option<usize> {
+        self.profile_menu
+    }
+
+    ///Return the name being typed for a new profile, if any
+    pub fn profile_name(&self) -> Option<&str> {
+        self.profile_name.as_deref()
+    }
+
+    ///Index of the display the current tab controls, if any. If the tab is a group, or was one,
+    ///the IO thread is told first so the members follow the leader
+    fn device_index(&mut self) -> Option<usize> {
+        let group = match self.state.tab() {
+            Some(Tab::Group(g)) => Some(g),
+            _ => None,
+        };
+        if group != self.group {
+            self.group = group;
+            self.dispatch(IoEvent::Group(group));
+        }
+        self.state.disp_index()
+    }
+
+    ///Return whether changes go to every display
+    pub fn linked(&self) -> bool {
+        self.linked
+    }
+
+    ///Return the color temperature presets
+    pub fn presets(&self) -> &[Preset] {
+        &self.presets
+    }
+
+    ///Return the selected row of the preset menu, or None if it is closed
+    pub fn preset_menu(&self) -> Option<usize> {
+        self.preset_menu
+    }
+
     ///Runs each tick
     pub fn update_on_tick(&mut self) -> AppReturn {
+        self.update_night_light(night_light::now());
         AppReturn::Continue
     }
 
+    ///Move the night light on to unix time now, and set the displays if that changed anything
+    pub fn update_night_light(&mut self, now: i64) {
+        let night_light = match &self.night_light {
+            Some(night_light) if self.state.is_initialized() => night_light,
+            _ => return,
+        };
+        let status = night_light.status(now);
+        self.night_light_status = Some(status);
+        if self.drives_night_light && self.night_light_target != Some(status.target) {
+            self.night_light_target = Some(status.target);
+            self.dispatch(IoEvent::NightLight(status.target));
+        }
+    }
+
+    ///Set whether the night light sets the displays, or is only shown
+    pub fn set_drives_night_light(&mut self, drives: bool) {
+        self.drives_night_light = drives;
+    }
+
+    ///Return where the night light is at, or None if it is off
+    pub fn night_light_status(&self) -> Option<&Status> {
+        self.night_light_status.as_ref()
+    }
+
     ///Send an IoEvent for the IO thread to complete
     pub fn dispatch(&mut self, action: IoEvent) {
         self.is_loading = true;
@@ -122,9 +414,19 @@ impl App {
         self.is_loading
     }
 
+    ///Set or clear the message in the status line
+    pub fn set_status(&mut self, status: Option<String>) {
+        self.status = status;
+    }
+
+    ///Return the message in the status line, if any
+    pub fn status(&self) -> Option<&str> {
+        self.status.as_deref()
+    }
+
     ///Initialize application
-    pub fn initialize(&mut self, disp_mgr: DispMgr) {
-        self.actions = vec![
+    pub fn initialize(&mut self, disps: Vec<Disp>) {
+        let actions = vec![
             Action::Quit,
             Action::MoveRight,
             Action::MoveLeft,
@@ -132,6 +434,14 @@ impl App {
             Action::MoveDown,
             Action::TabRight,
             Action::TabLeft,
+            Action::Reload,
+            Action::Presets,
+            Action::Profiles,
+            Action::Sync,
+            Action::Link,
+            Action::Undo,
+            Action::Redo,
+            Action::History,
             Action::Scale(1),
             Action::Scale(2),
             Action::Scale(3),
@@ -142,9 +452,14 @@ impl App {
             Action::Scale(8),
             Action::Scale(9),
             Action::Scale(0),
-        ]
-        .into();
-        self.state = AppState::initialize(disp_mgr);
+        ];
+        self.actions = Actions::new(actions, self.keymap().clone());
+        self.state = AppState::initialize(disps, self.groups.clone());
+    }
+
+    ///Return the effective key bindings
+    pub fn keymap(&self) -> &KeyMap {
+        self.actions.keymap()
     }
 
     ///Call when done loading
@@ -1,27 +1,40 @@
 use log::info;
-use crate::disp_mgr::{disp::DispProp, DispMgr};
+use crate::disp_mgr::disp::{Disp, DispProp};
+use crate::disp_mgr::group::Group;
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub enum AppState {
+    #[default]
     Uninit,
     Initialized {
         tab_index: usize,
         focused_prop: DispProp,
         num_disps: usize,
-        disp_mgr: DispMgr,
+        disps: Vec<Disp>,
+        ///Tabs for groups come after the displays, before the debug tab
+        groups: Vec<Group>,
     },
 }
 
+///What a tab shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tab {
+    Disp(usize),
+    Group(usize),
+    Debug,
+}
+
 impl AppState {
-    pub fn initialize(disp_mgr: DispMgr) -> Self {
+    pub fn initialize(disps: Vec<Disp>, groups: Vec<Group>) -> Self {
         let tab_index = 0;
         let focused_prop = DispProp::Brightness;
-        let num_disps = disp_mgr.get_num_disps();
+        let num_disps = disps.len();
         Self::Initialized {
             tab_index,
             focused_prop,
             num_disps,
-            disp_mgr,
+            disps,
+            groups,
         }
     }
 
@@ -44,17 +57,54 @@ impl AppState {
 
     ///Increment tab_index or cycle around if 1
     pub fn tab_right(&mut self) {
-        if let Self::Initialized { tab_index, num_disps, .. } = self {
-            *tab_index = (*tab_index + 1) % (*num_disps + 1)
+        if let Self::Initialized { tab_index, num_disps, groups, .. } = self {
+            *tab_index = (*tab_index + 1) % (*num_disps + groups.len() + 1)
         }
+        self.fix_focus();
     }
 
     ///Decrement tab_index or cycle around if 0
     pub fn tab_left(&mut self) {
-        if let Self::Initialized { tab_index, num_disps, .. } = self {
-            if *tab_index == 0 { *tab_index = *num_disps; }
+        if let Self::Initialized { tab_index, num_disps, groups, .. } = self {
+            if *tab_index == 0 { *tab_index = *num_disps + groups.len(); }
             else { *tab_index -= 1; }
         }
+        self.fix_focus();
+    }
+
+    ///Get what the current tab shows
+    pub fn tab(&self) -> Option<Tab> {
+        if let Self::Initialized { tab_index, num_disps, groups, .. } = self {
+            Some(if *tab_index < *num_disps {
+                Tab::Disp(*tab_index)
+            } else if *tab_index < *num_disps + groups.len() {
+                Tab::Group(*tab_index - *num_disps)
+            } else {
+                Tab::Debug
+            })
+        } else {
+            None
+        }
+    }
+
+    ///Get the index of the display the current tab controls. On a group tab that's the leader, if
+    ///it is connected
+    pub fn disp_index(&self) -> Option<usize> {
+        match (self, self.tab()?) {
+            (_, Tab::Disp(i)) => Some(i),
+            (Self::Initialized { disps, groups, .. }, Tab::Group(g)) => {
+                disps.iter().position(|d| d.name == groups[g].leader)
+            }
+            _ => None,
+        }
+    }
+
+    ///Get the groups
+    pub fn groups(&self) -> &[Group] {
+        match self {
+            Self::Initialized { groups, .. } => groups,
+            Self::Uninit => &[],
+        }
     }
 
     ///Get tab_index
@@ -66,10 +116,10 @@ impl AppState {
         }
     }
 
-    ///Get the disp_mgr. ONLY USED FOR CHECKING STATE, NOT CHANGING
-    pub fn disp_mgr(&self) -> Option<DispMgr> {
-        if let Self::Initialized { disp_mgr, .. } = self {
-            Some(disp_mgr.clone())
+    ///Get a copy of the displays. ONLY USED FOR CHECKING STATE, NOT CHANGING
+    pub fn disps(&self) -> Option<Vec<Disp>> {
+        if let Self::Initialized { disps, .. } = self {
+            Some(disps.clone())
         } else {
             None
         }
@@ -93,30 +143,56 @@ impl AppState {
         }
     }
 
-    ///Set the state of the disp_mgr. Set by the io thread.
-    pub fn set_disp_mgr(&mut self, new_disp_mgr: DispMgr) {
-        if let Self::Initialized { ref mut disp_mgr, .. } = self {
-            *disp_mgr = new_disp_mgr;
+    ///Set the state of the displays. Set by the io thread.
+    ///Displays may have come or gone, so the tab follows the display it was on, or moves to the
+    ///debug tab if that display is gone. Group tabs stay on their group
+    pub fn set_disps(&mut self, new_disps: Vec<Disp>) {
+        let tab = self.tab();
+        if let Self::Initialized { ref mut disps, tab_index, num_disps, groups, .. } = self {
+            let debug = new_disps.len() + groups.len();
+            *tab_index = match tab {
+                Some(Tab::Disp(i)) => new_disps
+                    .iter()
+                    .position(|d| d.name == disps[i].name)
+                    .unwrap_or(debug),
+                Some(Tab::Group(g)) => new_disps.len() + g,
+                _ => debug,
+            };
+            *num_disps = new_disps.len();
+            *disps = new_disps;
         }
+        self.fix_focus();
     }
 
     ///Move the selected ui slider to the next value
     pub fn next_prop(&mut self) {
-        if let Self::Initialized { focused_prop, .. } = self {
-            focused_prop.next();
+        let index = self.disp_index();
+        if let Self::Initialized { focused_prop, disps, .. } = self {
+            if let Some(disp) = index.and_then(|i| disps.get(i)) {
+                focused_prop.next(&disp.props());
+            }
         }
     }
 
     ///Move the selected ui slider to the previous value
     pub fn prev_prop(&mut self) {
-        if let Self::Initialized { focused_prop, .. } = self {
-            focused_prop.prev();
+        let index = self.disp_index();
+        if let Self::Initialized { focused_prop, disps, .. } = self {
+            if let Some(disp) = index.and_then(|i| disps.get(i)) {
+                focused_prop.prev(&disp.props());
+            }
         }
     }
-}
 
-impl Default for AppState {
-    fn default() -> Self {
-        Self::Uninit
+    ///Make sure the focused slider exists on the current tab. Not every display has every DispProp
+    fn fix_focus(&mut self) {
+        let index = self.disp_index();
+        if let Self::Initialized { focused_prop, disps, .. } = self {
+            if let Some(disp) = index.and_then(|i| disps.get(i)) {
+                if !disp.props().contains(focused_prop) {
+                    *focused_prop = DispProp::Brightness;
+                }
+            }
+        }
     }
 }
@@ -3,12 +3,16 @@ use tui::backend::Backend;
 use tui::layout::{Alignment, Constraint, Direction, Layout, Rect};
 use tui::style::{Color, Modifier, Style};
 use tui::text::{Span, Spans};
-use tui::widgets::{Block, BorderType, Borders, Cell, LineGauge, Row, Table, Tabs};
+use tui::widgets::{Block, BorderType, Borders, Cell, Clear, LineGauge, List, ListItem, ListState, Paragraph, Row, Table, Tabs};
 use tui::{symbols, Frame};
 use tui_logger::TuiLoggerWidget;
 use super::actions::Actions;
+use super::state::Tab;
 use crate::app::App;
-use crate::disp_mgr::disp::DispProp;
+use crate::config::Preset;
+use crate::disp_mgr::temperature;
+use crate::disp_mgr::disp::{Disp, DispProp};
+use crate::disp_mgr::history::History;
 
 ///Main draw function.
 pub fn draw<B>(rect: &mut Frame<B>, app: &App)
@@ -16,42 +20,83 @@ where
     B: Backend,
 {
     let size = rect.size();
-    check_size(&size);
+    if let Err(msg) = check_size(&size) {
+        rect.render_widget(Paragraph::new(msg).style(Style::default().fg(Color::Red)), size);
+        return;
+    }
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints(
             [
-                Constraint::Percentage(7),
-                Constraint::Percentage(93),
+                Constraint::Length(3),
+                Constraint::Min(0),
+                Constraint::Length(1),
             ]
             .as_ref(),
         )
         .split(size);
+    let night_light = app.night_light_status().map(|s| s.to_string()).unwrap_or_default();
+    let status_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Min(0), Constraint::Length(night_light.len() as u16)])
+        .split(chunks[2]);
+    rect.render_widget(draw_status(app.status()), status_chunks[0]);
+    rect.render_widget(Paragraph::new(night_light).style(Style::default().fg(Color::Yellow)), status_chunks[1]);
 
-    if let (Some(dm), Some(tab_index)) = (app.state().disp_mgr(), app.state().tab_index()) {
-        rect.render_widget(draw_tabs(&tab_index, dm.get_name_list()), chunks[0]);
-        if tab_index == dm.get_num_disps() {
-            draw_menu_debug(rect, app, chunks);
-        } else {
-            draw_menu_controller(rect, app, chunks, &tab_index);
+    if let (Some(disps), Some(tab_index), Some(tab)) = (app.state().disps(), app.state().tab_index(), app.state().tab()) {
+        let names = disps.iter().map(|d| d.name.as_str()).collect();
+        let groups = app.state().groups().iter().map(|g| g.name.as_str()).collect();
+        rect.render_widget(draw_tabs(&tab_index, names, groups, app.linked()), chunks[0]);
+        match tab {
+            Tab::Disp(i) => draw_menu_controller(rect, app, chunks.clone(), &i),
+            Tab::Group(g) => draw_menu_group(rect, app, chunks.clone(), g),
+            Tab::Debug => draw_menu_debug(rect, app, chunks.clone()),
         }
     }
+
+    if let Some(selected) = app.preset_menu() {
+        let presets = app.presets();
+        let area = centered(chunks[1], 44, presets.len() as u16 + 2);
+        let mut list_state = ListState::default();
+        list_state.select(Some(selected));
+        rect.render_widget(Clear, area);
+        rect.render_stateful_widget(draw_presets(presets), area, &mut list_state);
+    }
+
+    if let Some(selected) = app.profile_menu() {
+        let profiles = app.profiles();
+        let area = centered(chunks[1], 50, profiles.len() as u16 + 3);
+        let mut list_state = ListState::default();
+        list_state.select(Some(selected));
+        rect.render_widget(Clear, area);
+        rect.render_stateful_widget(draw_profiles(profiles, app.profile_name()), area, &mut list_state);
+    }
+
+    if app.history_menu() {
+        let history = app.history();
+        let rows = (history.done().len() + history.undone().len()).max(1);
+        let area = centered(chunks[1], 60, rows as u16 + 2);
+        rect.render_widget(Clear, area);
+        rect.render_widget(draw_history(history), area);
+    }
 }
 
-///Draw "tabs" at top of screen
-fn draw_tabs<'a>(index: &usize, mut names: Vec<&'a str>) -> Tabs<'a> {
-    names.push("Debug");
+///Draw "tabs" at top of screen. Groups come after the displays. The title says when changes go to
+///every display
+fn draw_tabs<'a>(index: &usize, names: Vec<&'a str>, groups: Vec<&'a str>, linked: bool) -> Tabs<'a> {
     let titles = names
         .iter()
-        .map(|t| {
-            Spans::from(vec![
-                Span::styled(*t, Style::default().fg(Color::White)),
-            ])
-        })
+        .map(|t| Spans::from(vec![Span::styled(*t, Style::default().fg(Color::White))]))
+        .chain(groups.iter().map(|t| Spans::from(vec![Span::styled(*t, Style::default().fg(Color::Cyan))])))
+        .chain(std::iter::once(Spans::from("Debug")))
         .collect();
+    let title = match linked {
+        true => Span::styled("tuib [linked]", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+        false => Span::raw("tuib"),
+    };
 
     Tabs::new(titles)
-        .block(Block::default().borders(Borders::ALL).title("tuib").title_alignment(Alignment::Center))
+        .block(Block::default().borders(Borders::ALL).title(title).title_alignment(Alignment::Center))
         .select(*index)
         .style(Style::default().fg(Color::White))
         .highlight_style(
@@ -86,35 +131,218 @@ pub fn draw_menu_controller<B>(rect: &mut Frame<B>, app: &App, chunks: Vec<Rect>
 where
     B: Backend,
 {
-    let body_chunks = Layout::default()
+    if let (Some(disps), Some(focused_prop)) = (app.state().disps(), app.state().focused_prop()) {
+        let disp = &disps[*tab_index];
+        let props: Vec<DispProp> = disp
+            .props()
+            .into_iter()
+            .filter(|p| !matches!(p, DispProp::Vcp(_)))
+            .collect();
+        let gauge_height = (rect.size().height / 12).max(2);
+
+        //monitors that speak DDC/CI get a second panel with their own settings
+        let (main_area, margin) = if disp.vcp.is_empty() {
+            (chunks[1], rect.size().width / 5)
+        } else {
+            let areas = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+                .split(chunks[1]);
+            draw_menu_vcp(rect, areas[1], disp, focused_prop, gauge_height);
+            (areas[0], rect.size().width / 10)
+        };
+
+        let body_chunks = stack(main_area, props.len(), gauge_height, margin);
+        for (i, prop) in props.iter().enumerate() {
+            let (title, color) = prop_style(*prop);
+            let title = match prop {
+                DispProp::Temperature => format!("{} ({}K)", title, disp.temperature),
+                _ => title.to_string(),
+            };
+            let ratio = (disp.get_value(*prop) as f64) / 100.0;
+            let gauge = draw_gauge(title, ratio, color, focused_prop, *prop);
+            rect.render_widget(gauge, body_chunks[i]);
+        }
+    }
+}
+
+///Draw a group: who is in it above the gauges of its leader
+pub fn draw_menu_group<B>(rect: &mut Frame<B>, app: &App, chunks: Vec<Rect>, group_index: usize)
+where
+    B: Backend,
+{
+    let group = &app.state().groups()[group_index];
+    let areas = Layout::default()
         .direction(Direction::Vertical)
-        .horizontal_margin((rect.size().width / 5) as u16)
-        .vertical_margin((rect.size().height / 3) as u16)
-        .constraints([
-                Constraint::Length((rect.size().height / 12) as u16),
-                Constraint::Length((rect.size().height / 12) as u16),
-                Constraint::Length((rect.size().height / 12) as u16),
-                Constraint::Length((rect.size().height / 12) as u16),
-            ].as_ref(),
-        )
+        .constraints([Constraint::Length(1), Constraint::Min(0)].as_ref())
         .split(chunks[1]);
+    let members = group.members.iter().map(|m| m.describe()).collect::<Vec<_>>().join(", ");
+    let header = format!("{} leads {}", group.leader, members);
+    rect.render_widget(Paragraph::new(header).alignment(Alignment::Center).style(Style::default().fg(Color::Cyan)), areas[0]);
 
-    if let (Some(dm), Some(focused_prop)) = (app.state().disp_mgr(), app.state().focused_prop()) {
-        // let disp = &app.state().disp_mgr().disps[*tab_index].clone();
-        let disp = dm.get_disp_by_index(*tab_index);
-        let brightness = (disp.brightness as f64) / 100.0;
-        let r = (disp.gamma.r as f64) / 100.0;
-        let g = (disp.gamma.g as f64) / 100.0;
-        let b = (disp.gamma.b as f64) / 100.0;
+    match app.state().disp_index() {
+        Some(i) => draw_menu_controller(rect, app, vec![chunks[0], areas[1], chunks[2]], &i),
+        None => {
+            let msg = format!("{} is not connected", group.leader);
+            rect.render_widget(Paragraph::new(msg).alignment(Alignment::Center).style(Style::default().fg(Color::Red)), areas[1]);
+        }
+    }
+}
 
-        let gauge = draw_gauge("Brightness".to_string(), brightness, Color::DarkGray, focused_prop, DispProp::Brightness);
-        rect.render_widget(gauge, body_chunks[0]);
-        let gauge = draw_gauge("Red".to_string(), r, Color::Red, focused_prop, DispProp::R);
-        rect.render_widget(gauge, body_chunks[1]);
-        let gauge = draw_gauge("Green".to_string(), g, Color::Green, focused_prop, DispProp::G);
-        rect.render_widget(gauge, body_chunks[2]);
-        let gauge = draw_gauge("Blue".to_string(), b, Color::Blue, focused_prop, DispProp::B);
-        rect.render_widget(gauge, body_chunks[3]);
+///Draw the panel of DDC/CI monitor settings. Continuous ones are gauges, the rest are pick-lists
+fn draw_menu_vcp<B>(rect: &mut Frame<B>, area: Rect, disp: &Disp, focused_prop: DispProp, gauge_height: u16)
+where
+    B: Backend,
+{
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Monitor")
+        .title_alignment(Alignment::Center);
+    let inner = block.inner(area);
+    rect.render_widget(block, area);
+
+    let body_chunks = stack(inner, disp.vcp.len(), gauge_height, 2);
+    for (i, feature) in disp.vcp.iter().enumerate() {
+        let prop = DispProp::Vcp(feature.code);
+        let title = feature.name().to_string();
+        if feature.is_list() {
+            let choice = draw_choice(title, feature.value_name(), focused_prop, prop);
+            rect.render_widget(choice, body_chunks[i]);
+        } else {
+            let ratio = (feature.pct() as f64) / 100.0;
+            let gauge = draw_gauge(title, ratio, Color::Magenta, focused_prop, prop);
+            rect.render_widget(gauge, body_chunks[i]);
+        }
+    }
+}
+
+///Draw the preset menu. Each preset has a swatch of its white point
+fn draw_presets(presets: &[Preset]) -> List<'_> {
+    let items: Vec<ListItem> = presets
+        .iter()
+        .map(|preset| {
+            let gamma = temperature::to_gamma(preset.kelvin);
+            let pct = |v: u8| (v as u16 * 255 / 100) as u8;
+            let swatch = Color::Rgb(pct(gamma.r), pct(gamma.g), pct(gamma.b));
+            ListItem::new(Spans::from(vec![
+                Span::styled("    ", Style::default().bg(swatch)),
+                Span::raw(format!(" {:<16}{:>6}K", preset.name, preset.kelvin)),
+            ]))
+        })
+        .collect();
+
+    List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Presets (enter: this display, a: all)")
+                .title_alignment(Alignment::Center),
+        )
+        .highlight_style(Style::default().fg(Color::Blue).add_modifier(Modifier::BOLD))
+        .highlight_symbol("> ")
+}
+
+///Draw the profile menu. The last row saves a new profile, and shows its name while it's typed
+fn draw_profiles<'a>(profiles: &'a [String], new_name: Option<&str>) -> List<'a> {
+    let mut items: Vec<ListItem> = profiles
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let key = if i < 9 { format!("{} ", i + 1) } else { String::from("  ") };
+            ListItem::new(Spans::from(vec![
+                Span::styled(key, Style::default().fg(Color::LightCyan)),
+                Span::raw(name.as_str()),
+            ]))
+        })
+        .collect();
+    let new = match new_name {
+        Some(name) => Span::styled(format!("  Name: {}_", name), Style::default().fg(Color::Yellow)),
+        None => Span::styled("  New profile...", Style::default().fg(Color::Gray)),
+    };
+    items.push(ListItem::new(new));
+
+    List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Profiles (enter: apply, w: save over)")
+                .title_alignment(Alignment::Center),
+        )
+        .highlight_style(Style::default().fg(Color::Blue).add_modifier(Modifier::BOLD))
+        .highlight_symbol("> ")
+}
+
+///Draw the history, newest first. Undone changes are grayed out above the ones that can be undone
+fn draw_history(history: &History) -> List<'static> {
+    let undone = history
+        .undone()
+        .iter()
+        .map(|entry| ListItem::new(Span::styled(format!("  {}", entry), Style::default().fg(Color::DarkGray))));
+    let done = history.done().iter().rev().enumerate().map(|(i, entry)| {
+        let mark = if i == 0 { "> " } else { "  " };
+        ListItem::new(Spans::from(vec![
+            Span::styled(mark, Style::default().fg(Color::LightCyan)),
+            Span::raw(entry.to_string()),
+        ]))
+    });
+    let mut items: Vec<ListItem> = undone.chain(done).collect();
+    if items.is_empty() {
+        items.push(ListItem::new(Span::styled("  Nothing to undo", Style::default().fg(Color::Gray))));
+    }
+
+    List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("History")
+            .title_alignment(Alignment::Center),
+    )
+}
+
+///A rect of at most width by height in the middle of area
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(area.x + (area.width - width) / 2, area.y + (area.height - height) / 2, width, height)
+}
+
+///Split area into n rows of the given height, centered vertically
+fn stack(area: Rect, n: usize, height: u16, horizontal_margin: u16) -> Vec<Rect> {
+    let mut constraints = vec![Constraint::Length(height); n];
+    constraints.push(Constraint::Min(0));
+    Layout::default()
+        .direction(Direction::Vertical)
+        .horizontal_margin(horizontal_margin)
+        .vertical_margin(area.height.saturating_sub(height * n as u16) / 2)
+        .constraints(constraints)
+        .split(area)
+}
+
+///Title and color of the gauge for a DispProp
+fn prop_style(prop: DispProp) -> (&'static str, Color) {
+    match prop {
+        DispProp::Brightness => ("Brightness", Color::DarkGray),
+        DispProp::Backlight => ("Backlight", Color::Yellow),
+        DispProp::Luminance => ("Luminance", Color::White),
+        DispProp::Temperature => ("Temperature", Color::LightYellow),
+        DispProp::R => ("Red", Color::Red),
+        DispProp::G => ("Green", Color::Green),
+        DispProp::B => ("Blue", Color::Blue),
+        DispProp::Vcp(_) => ("Monitor", Color::Magenta),
+    }
+}
+
+///Draw a pick-list showing the current choice. Drawn differently if selected.
+fn draw_choice(mut title: String, value: String, focused_prop: DispProp, prop: DispProp) -> Paragraph<'static> {
+    let value = format!("< {} >", value);
+    if focused_prop == prop {
+        title.push(']');
+        title.insert(0, '[');
+        let title = Span::styled(title, Style::default().add_modifier(Modifier::BOLD));
+        Paragraph::new(Span::styled(value, Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD)))
+            .block(Block::default().borders(Borders::NONE).title(title))
+    } else {
+        Paragraph::new(Span::styled(value, Style::default().fg(Color::Magenta)))
+            .block(Block::default().borders(Borders::NONE).title(title))
     }
 }
 
@@ -140,14 +368,14 @@ fn draw_gauge(mut title: String, mut ratio: f64, color: Color, focused_prop: Dis
 }
 
 //Draw menu that shows keybindings
-fn draw_help(actions: &Actions) -> Table {
+fn draw_help(actions: &Actions) -> Table<'_> {
     let key_style = Style::default().fg(Color::LightCyan);
     let help_style = Style::default().fg(Color::Gray);
 
     let mut rows = vec![];
     for action in actions.actions().iter() {
         let mut first = true;
-        for key in action.keys() {
+        for key in actions.keys(*action) {
             let help = if first {
                 first = false;
                 action.to_string()
@@ -190,12 +418,21 @@ fn draw_logs<'a>() -> TuiLoggerWidget<'a> {
         .style(Style::default().fg(Color::White).bg(Color::Black))
 }
 
+///Draw the status line. Shows the last error, if any
+fn draw_status(status: Option<&str>) -> Paragraph<'_> {
+    match status {
+        Some(msg) => Paragraph::new(Span::styled(format!("Error: {}", msg), Style::default().fg(Color::Red))),
+        None => Paragraph::new(""),
+    }
+}
+
 ///Ensure window size is valid
-fn check_size(rect: &Rect) {
+fn check_size(rect: &Rect) -> Result<(), String> {
     if rect.width < 52 {
-        panic!("Require width >= 52, (got {})", rect.width);
+        return Err(format!("Require width >= 52, (got {})", rect.width));
     }
     if rect.height < 28 {
-        panic!("Require height >= 28, (got {})", rect.height);
+        return Err(format!("Require height >= 28, (got {})", rect.height));
     }
+    Ok(())
 }
@@ -0,0 +1,591 @@
+use std::io::BufRead;
+use std::path::Path;
+use std::thread;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+use crate::disp_mgr::backend::DisplayBackend;
+use crate::disp_mgr::ddc::VcpFeature;
+use crate::disp_mgr::disp::{Disp, DispProp, Gamma};
+use crate::disp_mgr::error::{DispError, Result};
+use crate::disp_mgr::{scale_to_value, temperature, DispMgr};
+use crate::ipc::client::Client;
+
+///TUI brightness controller for multiple monitors. Starts the TUI when run without a command
+#[derive(Debug, Parser)]
+#[command(name = "tuib", version, about)]
+pub struct Cli {
+    ///Apply a saved profile and exit
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    ///Follow the [night_light] schedule from the config, without the TUI
+    NightLight,
+    ///Put the displays back the way tuib last left them
+    Restore,
+    ///Set values of displays
+    Set(Set),
+    ///Raise a value of displays
+    Inc(Step),
+    ///Lower a value of displays
+    Dec(Step),
+    ///Set a value of displays the way the 0-9 keys do: 1-9 is 10%-90%, 0 is 100%
+    Scale(Scale),
+    ///Print every display with its values and what it supports
+    List(List),
+    ///Print displays, or one value of them
+    Get(Get),
+    ///Own the displays and take requests on a Unix socket. The TUI and commands go through it
+    ///while it runs
+    Daemon(Daemon),
+    ///Print a line for a status bar every time the displays change. Scrolls and clicks read from
+    ///stdin move --prop. Needs a running daemon
+    Status(Status),
+}
+
+impl Command {
+    ///Check if the command can touch monitors over DDC/CI. Finding them means asking every i2c bus,
+    ///which is slow, and only luminance changes and reports need it
+    pub fn needs_ddc(&self) -> bool {
+        match self {
+            Command::Set(set) => set.luminance.is_some(),
+            Command::Inc(step) | Command::Dec(step) => step.prop == Prop::Luminance,
+            Command::Scale(scale) => scale.prop == Prop::Luminance,
+            _ => true,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct Daemon {
+    ///Also export org.tuib.Displays on the session bus
+    #[cfg(feature = "dbus")]
+    #[arg(long)]
+    pub dbus: bool,
+}
+
+///Displays a command changes
+#[derive(Debug, Clone, Args)]
+pub struct Outputs {
+    ///Output to change, eg eDP-1. Can be given more than once. Every display if left out
+    #[arg(short, long = "output", value_name = "NAME")]
+    pub outputs: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct Set {
+    #[command(flatten)]
+    pub outputs: Outputs,
+    ///Brightness in percent
+    #[arg(short, long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub brightness: Option<u8>,
+    ///Red, green and blue in percent, eg 100:90:80
+    #[arg(short, long, value_name = "R:G:B", value_parser = parse_gamma)]
+    pub gamma: Option<Gamma>,
+    ///Color temperature in Kelvin. A --gamma given with it wins
+    #[arg(short, long, value_parser = clap::value_parser!(u16).range(temperature::MIN as i64..=temperature::MAX as i64))]
+    pub temperature: Option<u16>,
+    ///Hardware backlight in percent
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub backlight: Option<u8>,
+    ///Monitor luminance over DDC/CI in percent
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub luminance: Option<u8>,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct Step {
+    #[command(flatten)]
+    pub outputs: Outputs,
+    #[arg(short, long, value_enum, default_value_t = Prop::Brightness)]
+    pub prop: Prop,
+    ///How far to move, in percent or in Kelvin for temperature. 5%, or 500K for temperature, if
+    ///left out
+    #[arg(short, long)]
+    pub step: Option<u16>,
+}
+
+#[derive(Debug, Args)]
+pub struct Scale {
+    #[command(flatten)]
+    pub outputs: Outputs,
+    #[arg(short, long, value_enum, default_value_t = Prop::Brightness)]
+    pub prop: Prop,
+    #[arg(value_parser = clap::value_parser!(u8).range(0..=9))]
+    pub scale: u8,
+}
+
+#[derive(Debug, Args)]
+pub struct List {
+    ///Print JSON instead of a table
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct Get {
+    #[command(flatten)]
+    pub outputs: Outputs,
+    ///Print just this value, one line per display. Temperature is in Kelvin, everything else in
+    ///percent
+    #[arg(short, long, value_enum)]
+    pub prop: Option<Prop>,
+    ///Print JSON. With --prop it's an object of output name to value
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct Status {
+    ///Displays to show, and what scrolling moves
+    #[command(flatten)]
+    pub step: Step,
+    ///Line printed for each display. Placeholders are {output}, {backend}, {brightness}, {temp},
+    ///{red}, {green}, {blue}, {backlight} and {luminance}
+    #[arg(short, long, default_value = DEFAULT_FORMAT, value_parser = parse_format)]
+    pub format: String,
+    ///Put between the lines of the displays
+    #[arg(long, default_value = "  ")]
+    pub separator: String,
+    ///Print waybar's JSON, with every display in the tooltip
+    #[arg(long)]
+    pub waybar: bool,
+    ///Print once and exit, for bars that run the command again on every click like i3blocks
+    #[arg(long)]
+    pub once: bool,
+}
+
+pub const DEFAULT_FORMAT: &str = "{output}: {brightness}%";
+
+///What the waybar tooltip shows for each display
+const TOOLTIP_FORMAT: &str = "{output}: {brightness}% {temp}K";
+
+///The DispProps the command line can change. Monitor settings are left to the TUI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Prop {
+    Brightness,
+    Backlight,
+    Luminance,
+    Temperature,
+    Red,
+    Green,
+    Blue,
+}
+
+impl From<Prop> for DispProp {
+    fn from(prop: Prop) -> Self {
+        match prop {
+            Prop::Brightness => DispProp::Brightness,
+            Prop::Backlight => DispProp::Backlight,
+            Prop::Luminance => DispProp::Luminance,
+            Prop::Temperature => DispProp::Temperature,
+            Prop::Red => DispProp::R,
+            Prop::Green => DispProp::G,
+            Prop::Blue => DispProp::B,
+        }
+    }
+}
+
+///Check that a status format only has placeholders fill knows
+fn parse_format(s: &str) -> std::result::Result<String, String> {
+    fill(s, &Report::new(&Disp::new(String::new(), 100, Gamma { r: 100, g: 100, b: 100 }), "")).map(|_| s.to_string())
+}
+
+///Replace the placeholders in format with the values of report. Values a display doesn't have
+///are -
+fn fill(format: &str, report: &Report) -> std::result::Result<String, String> {
+    let percent = |v: Option<u8>| v.map(|v| v.to_string()).unwrap_or_else(|| String::from("-"));
+    let mut line = String::new();
+    let mut rest = format;
+    while let Some(start) = rest.find('{') {
+        line.push_str(&rest[..start]);
+        let end = rest[start..].find('}').ok_or_else(|| format!("unclosed {{ in '{}'", format))? + start;
+        line.push_str(&match &rest[start + 1..end] {
+            "output" => report.output.clone(),
+            "backend" => report.backend.clone(),
+            "brightness" => report.brightness.to_string(),
+            "temp" | "temperature" => report.temperature.to_string(),
+            "red" => report.gamma.r.to_string(),
+            "green" => report.gamma.g.to_string(),
+            "blue" => report.gamma.b.to_string(),
+            "backlight" => percent(report.backlight),
+            "luminance" => percent(report.luminance),
+            name => return Err(format!("unknown placeholder {{{}}}", name)),
+        });
+        rest = &rest[end + 1..];
+    }
+    line.push_str(rest);
+    Ok(line)
+}
+
+///Parse r:g:b in percent
+fn parse_gamma(s: &str) -> std::result::Result<Gamma, String> {
+    let channels = s
+        .split(':')
+        .map(|c| match c.trim().parse::<u8>() {
+            Ok(v) if (1..=100).contains(&v) => Ok(v),
+            _ => Err(format!("'{}' is not a percentage from 1 to 100", c)),
+        })
+        .collect::<std::result::Result<Vec<u8>, String>>()?;
+    match channels[..] {
+        [r, g, b] => Ok(Gamma { r, g, b }),
+        _ => Err(format!("expected R:G:B, got '{}'", s)),
+    }
+}
+
+///Where commands go: straight to a DispMgr, or to a running daemon that owns one
+pub trait Displays {
+    ///Every display, in the order the backend lists them
+    fn reports(&mut self) -> Result<Vec<Report>>;
+
+    ///Set a DispProp of an output to a value
+    fn set_value(&mut self, output: &str, prop: DispProp, value: u8) -> Result<()>;
+
+    ///Move a DispProp of an output by delta, in Kelvin for Temperature and percent for everything
+    ///else
+    fn step_value(&mut self, output: &str, prop: DispProp, delta: i32) -> Result<()>;
+
+    fn set_temperature(&mut self, output: &str, kelvin: u16) -> Result<()>;
+}
+
+impl<B: DisplayBackend> Displays for DispMgr<B> {
+    fn reports(&mut self) -> Result<Vec<Report>> {
+        let backend = self.backend().name();
+        Ok(self.disps.iter().map(|d| Report::new(d, backend)).collect())
+    }
+
+    fn set_value(&mut self, output: &str, prop: DispProp, value: u8) -> Result<()> {
+        self.set_value_by_index(self.index_of(output)?, prop, value)
+    }
+
+    fn step_value(&mut self, output: &str, prop: DispProp, delta: i32) -> Result<()> {
+        self.step_value_by_index(self.index_of(output)?, prop, delta)
+    }
+
+    fn set_temperature(&mut self, output: &str, kelvin: u16) -> Result<()> {
+        self.set_temperature_by_index(self.index_of(output)?, kelvin)
+    }
+}
+
+impl Outputs {
+    ///The displays to change. Every display if no outputs were given
+    fn pick(&self, displays: &mut dyn Displays) -> Result<Vec<Disp>> {
+        let disps: Vec<Disp> = displays.reports()?.iter().map(Disp::from).collect();
+        if self.outputs.is_empty() {
+            return Ok(disps);
+        }
+        self.outputs
+            .iter()
+            .map(|o| disps.iter().find(|d| d.name == *o).cloned().ok_or_else(|| DispError::NoSuchOutput(o.clone())))
+            .collect()
+    }
+}
+
+///Fail if disp doesn't have prop, eg if it has no backlight
+fn check_supported(disp: &Disp, prop: DispProp) -> Result<()> {
+    if disp.props().contains(&prop) {
+        Ok(())
+    } else {
+        Err(DispError::Unsupported(format!("{} has no {}", disp.name, prop.to_string().to_lowercase())))
+    }
+}
+
+impl Set {
+    ///Set everything that was given on every display. Every display is tried even if one fails,
+    ///the first error is returned
+    pub fn run(&self, displays: &mut dyn Displays) -> Result<()> {
+        let mut result = Ok(());
+        for disp in self.outputs.pick(displays)? {
+            if let Some(kelvin) = self.temperature {
+                result = result.and(displays.set_temperature(&disp.name, kelvin));
+            }
+            let mut values = vec![];
+            if let Some(brightness) = self.brightness {
+                values.push((DispProp::Brightness, brightness));
+            }
+            if let Some(gamma) = self.gamma {
+                values.extend([(DispProp::R, gamma.r), (DispProp::G, gamma.g), (DispProp::B, gamma.b)]);
+            }
+            if let Some(backlight) = self.backlight {
+                values.push((DispProp::Backlight, backlight));
+            }
+            if let Some(luminance) = self.luminance {
+                values.push((DispProp::Luminance, luminance));
+            }
+            for (prop, value) in values {
+                result = result.and(check_supported(&disp, prop).and_then(|_| displays.set_value(&disp.name, prop, value)));
+            }
+        }
+        result
+    }
+}
+
+impl Step {
+    ///Move prop up, or down if up is false, on every display
+    pub fn run(&self, displays: &mut dyn Displays, up: bool) -> Result<()> {
+        let prop = DispProp::from(self.prop);
+        let step = match (self.step, prop) {
+            (Some(step), _) => step as i32,
+            (None, DispProp::Temperature) => 5 * temperature::STEP as i32,
+            (None, _) => 5,
+        };
+        let delta = if up { step } else { -step };
+        let mut result = Ok(());
+        for disp in self.outputs.pick(displays)? {
+            result = result.and(check_supported(&disp, prop).and_then(|_| displays.step_value(&disp.name, prop, delta)));
+        }
+        result
+    }
+}
+
+impl Scale {
+    pub fn run(&self, displays: &mut dyn Displays) -> Result<()> {
+        let prop = DispProp::from(self.prop);
+        let value = scale_to_value(self.scale);
+        let mut result = Ok(());
+        for disp in self.outputs.pick(displays)? {
+            result = result.and(check_supported(&disp, prop).and_then(|_| displays.set_value(&disp.name, prop, value)));
+        }
+        result
+    }
+}
+
+///What tuib knows about a display, for list, get and the daemon
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    pub output: String,
+    ///Backend that sets brightness and gamma, eg randr
+    pub backend: String,
+    pub brightness: u8,
+    ///Red, green and blue in percent
+    pub gamma: Gamma,
+    ///Color temperature in Kelvin
+    pub temperature: u16,
+    ///Hardware backlight in percent. None if the output has no backlight device
+    pub backlight: Option<u8>,
+    ///Monitor luminance over DDC/CI in percent. None if the monitor doesn't speak DDC/CI
+    pub luminance: Option<u8>,
+    ///Other DDC/CI features of the monitor
+    pub monitor: Vec<MonitorSetting>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MonitorSetting {
+    pub code: u8,
+    pub name: String,
+    pub value: u16,
+    pub max: u16,
+    ///Values a pick-list like the input source accepts. Empty for gauges
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub choices: Vec<u16>,
+    ///Name of the value, for pick-lists
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub choice: Option<String>,
+}
+
+impl Report {
+    pub fn new(disp: &Disp, backend: &str) -> Self {
+        let monitor = disp
+            .vcp
+            .iter()
+            .map(|f| MonitorSetting {
+                code: f.code,
+                name: f.name().to_string(),
+                value: f.cur,
+                max: f.max,
+                choices: f.choices.clone(),
+                choice: f.is_list().then(|| f.value_name()),
+            })
+            .collect();
+        Report {
+            output: disp.name.clone(),
+            backend: backend.to_string(),
+            brightness: disp.brightness,
+            gamma: disp.gamma,
+            temperature: disp.temperature,
+            backlight: disp.backlight,
+            luminance: disp.luminance,
+            monitor,
+        }
+    }
+}
+
+impl From<&Report> for Disp {
+    fn from(report: &Report) -> Self {
+        let mut disp = Disp::new(report.output.clone(), report.brightness, report.gamma);
+        disp.temperature = report.temperature;
+        disp.backlight = report.backlight;
+        disp.luminance = report.luminance;
+        disp.vcp = report
+            .monitor
+            .iter()
+            .map(|m| VcpFeature { code: m.code, cur: m.value, max: m.max, choices: m.choices.clone() })
+            .collect();
+        disp
+    }
+}
+
+///Reports of the displays outputs picks, in the order they were asked for
+fn pick_reports(displays: &mut dyn Displays, outputs: &Outputs) -> Result<Vec<Report>> {
+    let reports = displays.reports()?;
+    if outputs.outputs.is_empty() {
+        return Ok(reports);
+    }
+    outputs
+        .outputs
+        .iter()
+        .map(|o| reports.iter().find(|r| r.output == *o).cloned().ok_or_else(|| DispError::NoSuchOutput(o.clone())))
+        .collect()
+}
+
+///Reports as JSON, or as a table with a column per value
+fn format_reports(reports: &[Report], json: bool) -> Result<String> {
+    if json {
+        return serde_json::to_string(reports).map_err(|e| DispError::Parse(e.to_string()));
+    }
+    let percent = |v: Option<u8>| v.map(|v| format!("{}%", v)).unwrap_or_else(|| String::from("-"));
+    let mut rows = vec![["OUTPUT", "BACKEND", "BRIGHTNESS", "GAMMA", "TEMPERATURE", "BACKLIGHT", "LUMINANCE", "MONITOR"]
+        .map(String::from)];
+    for r in reports {
+        let monitor: Vec<String> = r
+            .monitor
+            .iter()
+            .map(|m| format!("{}={}", m.name, m.choice.clone().unwrap_or_else(|| format!("{}/{}", m.value, m.max))))
+            .collect();
+        rows.push([
+            r.output.clone(),
+            r.backend.clone(),
+            format!("{}%", r.brightness),
+            format!("{}:{}:{}", r.gamma.r, r.gamma.g, r.gamma.b),
+            format!("{}K", r.temperature),
+            percent(r.backlight),
+            percent(r.luminance),
+            if monitor.is_empty() { String::from("-") } else { monitor.join(", ") },
+        ]);
+    }
+    let widths: Vec<usize> = (0..8).map(|c| rows.iter().map(|row| row[c].len()).max().unwrap_or(0)).collect();
+    let lines: Vec<String> = rows
+        .iter()
+        .map(|row| {
+            let cells: Vec<String> = row.iter().zip(&widths).map(|(cell, w)| format!("{:w$}", cell, w = w)).collect();
+            cells.join("  ").trim_end().to_string()
+        })
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+impl List {
+    ///What to print
+    pub fn run(&self, displays: &mut dyn Displays) -> Result<String> {
+        format_reports(&displays.reports()?, self.json)
+    }
+}
+
+impl Get {
+    ///What to print. Fails if a display doesn't have the prop asked for
+    pub fn run(&self, displays: &mut dyn Displays) -> Result<String> {
+        let reports = pick_reports(displays, &self.outputs)?;
+        let prop = match self.prop {
+            Some(prop) => DispProp::from(prop),
+            None => return format_reports(&reports, self.json),
+        };
+        let mut values = vec![];
+        for disp in reports.iter().map(Disp::from) {
+            check_supported(&disp, prop)?;
+            let value = match prop {
+                DispProp::Temperature => disp.temperature,
+                _ => disp.get_value(prop) as u16,
+            };
+            values.push((disp.name, value));
+        }
+        if self.json {
+            let map: serde_json::Map<String, serde_json::Value> =
+                values.into_iter().map(|(name, value)| (name, value.into())).collect();
+            return Ok(serde_json::Value::Object(map).to_string());
+        }
+        Ok(values.iter().map(|(_, value)| value.to_string()).collect::<Vec<_>>().join("\n"))
+    }
+}
+
+impl Status {
+    ///What to print for reports, which are every display the daemon has
+    pub fn render(&self, reports: &[Report]) -> Result<String> {
+        let reports = match self.step.outputs.outputs.is_empty() {
+            true => reports.to_vec(),
+            false => reports.iter().filter(|r| self.step.outputs.outputs.contains(&r.output)).cloned().collect(),
+        };
+        let lines = |format: &str, separator: &str| -> Result<String> {
+            let lines = reports.iter().map(|r| fill(format, r)).collect::<std::result::Result<Vec<_>, _>>();
+            Ok(lines.map_err(DispError::Parse)?.join(separator))
+        };
+        let text = lines(&self.format, &self.separator)?;
+        if !self.waybar {
+            return Ok(text);
+        }
+        let waybar = serde_json::json!({
+            "text": text,
+            "tooltip": lines(TOOLTIP_FORMAT, "\n")?,
+            "percentage": reports.first().map(|r| r.brightness),
+            "class": "tuib",
+        });
+        Ok(waybar.to_string())
+    }
+
+    ///What a line from the status bar asks for: Some(true) to move up, Some(false) to move down,
+    ///None for anything else. Takes i3blocks' button numbers, bare or in its click JSON, where 4 is
+    ///scroll up and 5 scroll down, and the words up, down, inc and dec
+    pub fn parse_action(line: &str) -> Option<bool> {
+        let line = line.trim();
+        let button = match serde_json::from_str::<serde_json::Value>(line) {
+            Ok(serde_json::Value::Object(click)) => click.get("button")?.as_u64()?.to_string(),
+            _ => line.to_lowercase(),
+        };
+        match button.as_str() {
+            "4" | "up" | "inc" => Some(true),
+            "5" | "down" | "dec" => Some(false),
+            _ => None,
+        }
+    }
+
+    ///Print a line every time the displays change, until the daemon at socket goes away. With
+    ///--once, print one and return. A click i3blocks passes in $BLOCK_BUTTON is done first
+    pub fn run(&self, socket: &Path) -> Result<()> {
+        let mut client = Client::connect(socket)
+            .map_err(|_| DispError::Daemon(format!("status needs tuib daemon running on {}", socket.display())))?;
+        if let Some(up) = std::env::var("BLOCK_BUTTON").ok().and_then(|b| Status::parse_action(&b)) {
+            self.step.run(&mut client, up)?;
+        }
+        if self.once {
+            println!("{}", self.render(&client.reports()?)?);
+            return Ok(());
+        }
+
+        //clicks and scrolls come in on stdin while lines go out
+        let mut actions = Client::connect(socket)?;
+        let step = self.step.clone();
+        thread::spawn(move || {
+            for line in std::io::stdin().lock().lines().map_while(std::result::Result::ok) {
+                if let Some(up) = Status::parse_action(&line) {
+                    if let Err(e) = step.run(&mut actions, up) {
+                        eprintln!("tuib: {}", e);
+                    }
+                }
+            }
+        });
+
+        let mut last = None;
+        for reports in client.subscribe()? {
+            let line = self.render(&reports)?;
+            //a change to something the line doesn't show isn't worth printing
+            if last.as_ref() != Some(&line) {
+                println!("{}", line);
+                last = Some(line);
+            }
+        }
+        Err(DispError::Daemon(String::from("the daemon went away")))
+    }
+}
@@ -0,0 +1,128 @@
+use std::fmt::{self, Display};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::Deserialize;
+use crate::app::actions::KeyMap;
+use crate::disp_mgr::group::Group;
+use crate::night_light::NightLightConfig;
+
+///Settings read from config.toml. Everything is optional, a missing file is the same as an empty one
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    ///Color temperature presets, added to the built in ones. A preset with the name of a built in
+    ///one replaces it
+    pub presets: Vec<Preset>,
+    ///Change temperature and brightness with the sun. Off unless set
+    pub night_light: Option<NightLightConfig>,
+    ///How long the scale keys take to fade to their value, in milliseconds. 0 jumps straight there
+    pub fade_ms: u64,
+    ///Displays that move together, each shown as a tab of its own
+    pub groups: Vec<Group>,
+    ///Key bindings on top of the defaults
+    pub keys: KeyMap,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            presets: Vec::new(),
+            night_light: None,
+            fade_ms: 250,
+            groups: Vec::new(),
+            keys: KeyMap::default(),
+        }
+    }
+}
+
+///A named color temperature, picked from the preset menu
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    pub kelvin: u16,
+}
+
+impl Preset {
+    pub fn new(name: &str, kelvin: u16) -> Self {
+        Preset { name: name.to_string(), kelvin }
+    }
+}
+
+///Why the config couldn't be loaded
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "could not read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "invalid config {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    ///Directory tuib keeps its config in: $XDG_CONFIG_HOME/tuib, or ~/.config/tuib
+    pub fn dir() -> Option<PathBuf> {
+        let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(dir.join("tuib"))
+    }
+
+    ///Where the config lives
+    pub fn path() -> Option<PathBuf> {
+        Some(Config::dir()?.join("config.toml"))
+    }
+
+    ///Load the config from the default path
+    pub fn load() -> Result<Config, ConfigError> {
+        match Config::path() {
+            Some(path) => Config::load_from(&path),
+            None => Ok(Config::default()),
+        }
+    }
+
+    ///Load the config from a file. A file that doesn't exist gives the default config
+    pub fn load_from(path: &Path) -> Result<Config, ConfigError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Config::parse(&text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(ConfigError::Io(path.to_path_buf(), e)),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Config, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    pub fn fade(&self) -> Duration {
+        Duration::from_millis(self.fade_ms)
+    }
+
+    ///The built in presets followed by the ones from the config
+    pub fn presets(&self) -> Vec<Preset> {
+        let mut presets = vec![
+            Preset::new("Candle", 1900),
+            Preset::new("Incandescent", 2700),
+            Preset::new("Halogen", 3400),
+            Preset::new("Daylight", 5500),
+            Preset::new("Neutral", 6500),
+            Preset::new("Overcast", 7000),
+        ];
+        for preset in &self.presets {
+            match presets.iter_mut().find(|p| p.name == preset.name) {
+                Some(p) => p.kelvin = preset.kelvin,
+                None => presets.push(preset.clone()),
+            }
+        }
+        presets
+    }
+}
@@ -0,0 +1,111 @@
+use std::sync::Arc;
+use parking_lot::Mutex;
+use super::{DisplayBackend, Notify};
+use crate::disp_mgr::disp::{Disp, Gamma};
+use crate::disp_mgr::error::{DispError, Result};
+
+///Backend that keeps everything in memory and records every Disp it is asked to apply, so
+///DispMgr and everything above it can be driven without a display server.
+///Clones share the same state, so keep one around to inspect after handing the other to DispMgr.
+#[derive(Clone, Default)]
+pub struct Mock {
+    state: Arc<Mutex<MockState>>,
+    watchers: Arc<Mutex<Vec<Notify>>>,
+}
+
+#[derive(Default)]
+struct MockState {
+    outputs: Vec<Disp>,
+    applied: Vec<Disp>,
+    fail: bool,
+}
+
+impl Mock {
+    pub fn new() -> Self {
+        Mock::default()
+    }
+
+    ///Add a connected output with a starting brightness and gamma
+    pub fn with_output(self, name: &str, brightness: u8, gamma: Gamma) -> Self {
+        self.connect(name, brightness, gamma);
+        self
+    }
+
+    ///Plug in an output. Anything watching is notified
+    pub fn connect(&self, name: &str, brightness: u8, gamma: Gamma) {
+        let disp = Disp::new(name.to_string(), brightness, gamma);
+        self.state.lock().outputs.push(disp);
+        self.notify();
+    }
+
+    ///Unplug an output. Anything watching is notified
+    pub fn disconnect(&self, name: &str) {
+        self.state.lock().outputs.retain(|d| d.name != name);
+        self.notify();
+    }
+
+    fn notify(&self) {
+        for notify in self.watchers.lock().iter() {
+            notify();
+        }
+    }
+
+    ///Make every call fail from now on, or stop failing
+    pub fn set_fail(&self, fail: bool) {
+        self.state.lock().fail = fail;
+    }
+
+    ///Every Disp applied so far, oldest first
+    pub fn applied(&self) -> Vec<Disp> {
+        self.state.lock().applied.clone()
+    }
+
+    ///The state an output would currently be in
+    pub fn output(&self, name: &str) -> Option<Disp> {
+        self.state.lock().outputs.iter().find(|d| d.name == name).cloned()
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.state.lock().fail {
+            return Err(DispError::Command("mock backend told to fail".to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl DisplayBackend for Mock {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn outputs(&mut self) -> Result<Vec<String>> {
+        self.check()?;
+        Ok(self.state.lock().outputs.iter().map(|d| d.name.clone()).collect())
+    }
+
+    fn read_state(&mut self, output: &str) -> Result<(u8, Gamma)> {
+        self.check()?;
+        self.output(output)
+            .map(|d| (d.brightness, d.gamma))
+            .ok_or_else(|| DispError::NoSuchOutput(output.to_string()))
+    }
+
+    fn apply(&mut self, disp: &Disp) -> Result<()> {
+        self.check()?;
+        let mut state = self.state.lock();
+        let output = state
+            .outputs
+            .iter_mut()
+            .find(|d| d.name == disp.name)
+            .ok_or_else(|| DispError::NoSuchOutput(disp.name.clone()))?;
+        output.brightness = disp.brightness;
+        output.gamma = disp.gamma;
+        state.applied.push(disp.clone());
+        Ok(())
+    }
+
+    fn watch(&self, notify: Notify) -> bool {
+        self.watchers.lock().push(notify);
+        true
+    }
+}
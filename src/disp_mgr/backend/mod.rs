@@ -0,0 +1,81 @@
+use log::warn;
+use super::disp::{Disp, Gamma};
+use super::error::Result;
+
+pub mod mock;
+pub mod randr;
+pub mod ramp;
+#[cfg(feature = "wayland")]
+pub mod wayland;
+pub mod xrandr;
+pub use mock::Mock;
+pub use randr::Randr;
+#[cfg(feature = "wayland")]
+pub use wayland::Wayland;
+pub use xrandr::Xrandr;
+
+///Something that knows how to find displays and change their brightness and gamma.
+///DispMgr holds one of these and calls into it whenever a Disp changes.
+pub trait DisplayBackend {
+    ///Short name of the backend, eg randr
+    fn name(&self) -> &'static str;
+
+    ///Return the names of every connected output
+    fn outputs(&mut self) -> Result<Vec<String>>;
+
+    ///Read the current brightness and gamma of an output
+    fn read_state(&mut self, output: &str) -> Result<(u8, Gamma)>;
+
+    ///Apply the brightness and gamma stored in disp to the output it names
+    fn apply(&mut self, disp: &Disp) -> Result<()>;
+
+    ///Call notify whenever outputs are connected or disconnected, from a thread of the backend's
+    ///own. Returns false if the backend can't tell, in which case the caller has to poll
+    fn watch(&self, _notify: Notify) -> bool {
+        false
+    }
+}
+
+///Callback for DisplayBackend::watch
+pub type Notify = Box<dyn Fn() + Send>;
+
+impl<T: DisplayBackend + ?Sized> DisplayBackend for Box<T> {
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+
+    fn outputs(&mut self) -> Result<Vec<String>> {
+        (**self).outputs()
+    }
+
+    fn read_state(&mut self, output: &str) -> Result<(u8, Gamma)> {
+        (**self).read_state(output)
+    }
+
+    fn apply(&mut self, disp: &Disp) -> Result<()> {
+        (**self).apply(disp)
+    }
+
+    fn watch(&self, notify: Notify) -> bool {
+        (**self).watch(notify)
+    }
+}
+
+///Pick the best backend available. Use wlr-gamma-control when running under wayland, otherwise
+///talk to RandR directly if possible, and fall back to shelling out to xrandr.
+pub fn detect() -> Box<dyn DisplayBackend + Send> {
+    #[cfg(feature = "wayland")]
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        match Wayland::new() {
+            Ok(wayland) => return Box::new(wayland),
+            Err(e) => warn!("Could not use wlr-gamma-control ({}), trying X", e),
+        }
+    }
+    match Randr::new() {
+        Ok(randr) => Box::new(randr),
+        Err(e) => {
+            warn!("Could not use RandR directly ({}), falling back to xrandr", e);
+            Box::new(Xrandr::default())
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use crate::disp_mgr::disp::Gamma;
+
+///Build one channel of a gamma ramp. Mirrors what `xrandr --gamma g --brightness b` uploads:
+///entry i is (i / (size - 1)) ^ (1 / g) * b, clamped to 1 and scaled to u16.
+pub fn ramp(size: usize, gamma: u8, brightness: u8) -> Vec<u16> {
+    let exponent = 100.0 / (gamma.max(1) as f64);
+    let brightness = (brightness as f64) / 100.0;
+    let last = (size.max(2) - 1) as f64;
+    (0..size)
+        .map(|i| {
+            let v = ((i as f64) / last).powf(exponent) * brightness;
+            (v.min(1.0) * 65535.0) as u16
+        })
+        .collect()
+}
+
+///Inverse of ramp. Recover brightness and gamma from the three channels of a crtc gamma ramp.
+///Brightness is the brightest last entry, and each channel's exponent is found by looking at the
+///middle of the part of the ramp that isn't clamped at the top, like xrandr --verbose does.
+pub fn estimate(red: &[u16], green: &[u16], blue: &[u16]) -> (u8, Gamma) {
+    let last = |r: &[u16]| r.last().copied().unwrap_or(u16::MAX) as f64 / 65535.0;
+    let brightness = last(red).max(last(green)).max(last(blue));
+    if brightness <= 0.0 {
+        return (1, Gamma { r: 100, g: 100, b: 100 });
+    }
+
+    let exponent = |r: &[u16]| {
+        if r.len() < 2 {
+            return 1.0;
+        }
+        //skip the part of the ramp that has been clamped to the max
+        let mut top = r.len() - 1;
+        while top > 0 && r[top - 1] == u16::MAX {
+            top -= 1;
+        }
+        //very low gammas are flat at zero for a while, look further up if needed
+        let mut i = top / 2;
+        while i < top && r[i] == 0 {
+            i += 1;
+        }
+        let x = (i as f64) / ((r.len() - 1) as f64);
+        let y = (r[i] as f64) / 65535.0 / brightness;
+        if x <= 0.0 || y <= 0.0 {
+            return 1.0;
+        }
+        (y.ln() / x.ln()) as f32
+    };
+
+    let brightness = (brightness * 100.0).round().clamp(1.0, 100.0) as u8;
+    let gamma = Gamma {
+        r: Gamma::pct_from_exponent(exponent(red)),
+        g: Gamma::pct_from_exponent(exponent(green)),
+        b: Gamma::pct_from_exponent(exponent(blue)),
+    };
+    (brightness, gamma)
+}
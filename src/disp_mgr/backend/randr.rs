@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::thread;
+use log::warn;
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::{self, ConnectionExt as _};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use super::ramp::{estimate, ramp};
+use super::{DisplayBackend, Notify};
+use crate::disp_mgr::disp::{Disp, Gamma};
+use crate::disp_mgr::error::{DispError, Result};
+
+///Backend that talks to the X server over the RandR extension. Nothing is spawned, changes are
+///applied by uploading a gamma ramp to the CRTC driving each output, the same way xrandr does it.
+pub struct Randr {
+    display: Option<String>,
+    conn: RustConnection,
+    root: u32,
+    crtcs: HashMap<String, randr::Crtc>,
+}
+
+impl Randr {
+    ///Connect to the display named by $DISPLAY
+    pub fn new() -> Result<Self> {
+        Randr::connect(None)
+    }
+
+    ///Connect to a specific display, eg ":99" for an Xvfb instance
+    pub fn connect(display: Option<&str>) -> Result<Self> {
+        let (conn, screen_num) = x11rb::connect(display)?;
+        let root = conn.setup().roots[screen_num].root;
+        //gamma ramps need randr >= 1.2
+        let version = conn.randr_query_version(1, 2)?.reply()?;
+        if (version.major_version, version.minor_version) < (1, 2) {
+            return Err(DispError::Unsupported(format!(
+                "RandR {}.{} is too old, need at least 1.2",
+                version.major_version,
+                version.minor_version
+            )));
+        }
+        Ok(Randr {
+            display: display.map(str::to_string),
+            conn,
+            root,
+            crtcs: HashMap::new(),
+        })
+    }
+
+    fn crtc(&self, output: &str) -> Result<randr::Crtc> {
+        self.crtcs
+            .get(output)
+            .copied()
+            .ok_or_else(|| DispError::NoSuchOutput(output.to_string()))
+    }
+}
+
+impl DisplayBackend for Randr {
+    fn name(&self) -> &'static str {
+        "randr"
+    }
+
+    fn outputs(&mut self) -> Result<Vec<String>> {
+        let resources = self.conn.randr_get_screen_resources_current(self.root)?.reply()?;
+        let mut names = Vec::new();
+        self.crtcs.clear();
+        for output in resources.outputs {
+            let info = self
+                .conn
+                .randr_get_output_info(output, resources.config_timestamp)?
+                .reply()?;
+            //outputs that are connected but switched off have no crtc and nothing to adjust
+            if info.connection != randr::Connection::CONNECTED || info.crtc == x11rb::NONE {
+                continue;
+            }
+            let name = String::from_utf8_lossy(&info.name).to_string();
+            self.crtcs.insert(name.clone(), info.crtc);
+            names.push(name);
+        }
+        Ok(names)
+    }
+
+    fn read_state(&mut self, output: &str) -> Result<(u8, Gamma)> {
+        let crtc = self.crtc(output)?;
+        let ramps = self.conn.randr_get_crtc_gamma(crtc)?.reply()?;
+        Ok(estimate(&ramps.red, &ramps.green, &ramps.blue))
+    }
+
+    fn apply(&mut self, disp: &Disp) -> Result<()> {
+        let crtc = self.crtc(&disp.name)?;
+        let size = self.conn.randr_get_crtc_gamma_size(crtc)?.reply()?.size as usize;
+        let red = ramp(size, disp.gamma.r, disp.brightness);
+        let green = ramp(size, disp.gamma.g, disp.brightness);
+        let blue = ramp(size, disp.gamma.b, disp.brightness);
+        self.conn.randr_set_crtc_gamma(crtc, &red, &green, &blue)?.check()?;
+        Ok(())
+    }
+
+    ///Listen for RandR screen and output change notifications on a connection of its own
+    fn watch(&self, notify: Notify) -> bool {
+        let conn = match subscribe(self.display.as_deref()) {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!("Could not subscribe to RandR notifications: {}", e);
+                return false;
+            }
+        };
+        thread::spawn(move || loop {
+            match conn.wait_for_event() {
+                Ok(Event::RandrScreenChangeNotify(_)) | Ok(Event::RandrNotify(_)) => {
+                    //plugging something in sends a burst of events, only reload once
+                    while let Ok(Some(_)) = conn.poll_for_event() {}
+                    notify();
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("Lost RandR notifications: {}", e);
+                    break;
+                }
+            }
+        });
+        true
+    }
+}
+
+fn subscribe(display: Option<&str>) -> Result<RustConnection> {
+    let (conn, screen_num) = x11rb::connect(display)?;
+    let root = conn.setup().roots[screen_num].root;
+    let mask = randr::NotifyMask::SCREEN_CHANGE | randr::NotifyMask::OUTPUT_CHANGE;
+    conn.randr_select_input(root, mask)?.check()?;
+    Ok(conn)
+}
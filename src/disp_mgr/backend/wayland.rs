@@ -0,0 +1,313 @@
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
+use std::os::unix::io::{AsFd, FromRawFd};
+use std::thread;
+use log::warn;
+use wayland_client::protocol::{wl_output, wl_registry};
+use wayland_client::{Connection, Dispatch, EventQueue, Proxy, QueueHandle};
+use wayland_protocols_wlr::gamma_control::v1::client::{
+    zwlr_gamma_control_manager_v1::ZwlrGammaControlManagerV1,
+    zwlr_gamma_control_v1::{self, ZwlrGammaControlV1},
+};
+use super::ramp::ramp;
+use super::{DisplayBackend, Notify};
+use crate::disp_mgr::disp::{Disp, Gamma};
+use crate::disp_mgr::error::{DispError, Result};
+
+///Backend for wlroots based compositors (sway, river, hyprland...) through wlr-gamma-control.
+///The protocol has no way to read gamma back, so the last applied state is remembered instead.
+///Compositors restore the original gamma as soon as the client that set it disconnects, so
+///changes only last as long as tuib is running.
+pub struct Wayland {
+    conn: Connection,
+    queue: EventQueue<State>,
+    state: State,
+}
+
+#[derive(Default)]
+struct State {
+    manager: Option<ZwlrGammaControlManagerV1>,
+    outputs: Vec<Output>,
+}
+
+struct Output {
+    ///registry name of the wl_output global, used to notice it going away
+    global: u32,
+    wl: wl_output::WlOutput,
+    name: Option<String>,
+    control: Option<ZwlrGammaControlV1>,
+    gamma_size: Option<u32>,
+    ///set when another client already controls this output's gamma
+    failed: bool,
+    applied: (u8, Gamma),
+}
+
+impl Wayland {
+    ///Connect to the compositor named by $WAYLAND_DISPLAY
+    pub fn new() -> Result<Self> {
+        let conn = Connection::connect_to_env()?;
+        let mut queue = conn.new_event_queue();
+        let qh = queue.handle();
+        conn.display().get_registry(&qh, ());
+
+        let mut state = State::default();
+        //first roundtrip announces the globals, the second the output names
+        queue.roundtrip(&mut state)?;
+        queue.roundtrip(&mut state)?;
+        if state.manager.is_none() {
+            return Err(DispError::Unsupported("compositor does not support wlr-gamma-control".to_string()));
+        }
+
+        let mut wayland = Wayland { conn, queue, state };
+        wayland.sync()?;
+        Ok(wayland)
+    }
+
+    ///Process pending events and make sure every output has a gamma control
+    fn sync(&mut self) -> Result<()> {
+        self.queue.roundtrip(&mut self.state)?;
+        let qh = self.queue.handle();
+        if let Some(manager) = &self.state.manager {
+            for output in self.state.outputs.iter_mut() {
+                if output.control.is_none() {
+                    output.control = Some(manager.get_gamma_control(&output.wl, &qh, ()));
+                }
+            }
+        }
+        //wait for the gamma_size (or failed) events of new controls
+        self.queue.roundtrip(&mut self.state)?;
+        Ok(())
+    }
+
+    fn output(&self, name: &str) -> Result<&Output> {
+        self.state
+            .outputs
+            .iter()
+            .find(|o| o.name.as_deref() == Some(name))
+            .ok_or_else(|| DispError::NoSuchOutput(name.to_string()))
+    }
+}
+
+impl DisplayBackend for Wayland {
+    fn name(&self) -> &'static str {
+        "wayland"
+    }
+
+    fn outputs(&mut self) -> Result<Vec<String>> {
+        self.sync()?;
+        Ok(self
+            .state
+            .outputs
+            .iter()
+            .filter(|o| !o.failed && o.gamma_size.is_some())
+            .filter_map(|o| o.name.clone())
+            .collect())
+    }
+
+    fn read_state(&mut self, output: &str) -> Result<(u8, Gamma)> {
+        Ok(self.output(output)?.applied)
+    }
+
+    fn apply(&mut self, disp: &Disp) -> Result<()> {
+        let output = self.output(&disp.name)?;
+        let (control, size) = match (&output.control, output.gamma_size) {
+            (Some(control), Some(size)) if !output.failed => (control, size as usize),
+            _ => return Err(DispError::Wayland(format!("gamma of {} is controlled by another client", disp.name))),
+        };
+
+        //the compositor wants all three channels back to back in one file
+        let mut table = ramp(size, disp.gamma.r, disp.brightness);
+        table.extend(ramp(size, disp.gamma.g, disp.brightness));
+        table.extend(ramp(size, disp.gamma.b, disp.brightness));
+        let bytes: Vec<u8> = table.iter().flat_map(|v| v.to_ne_bytes()).collect();
+        let mut file = memfd("tuib-gamma")?;
+        file.write_all(&bytes)?;
+        file.seek(SeekFrom::Start(0))?;
+
+        control.set_gamma(file.as_fd());
+        self.conn.flush()?;
+        self.queue.roundtrip(&mut self.state)?;
+
+        let output = self
+            .state
+            .outputs
+            .iter_mut()
+            .find(|o| o.name.as_deref() == Some(disp.name.as_str()))
+            .ok_or_else(|| DispError::NoSuchOutput(disp.name.clone()))?;
+        if output.failed {
+            return Err(DispError::Wayland(format!("compositor rejected gamma table for {}", disp.name)));
+        }
+        output.applied = (disp.brightness, disp.gamma);
+        Ok(())
+    }
+
+    ///Listen for wl_output globals coming and going on a connection of its own
+    fn watch(&self, notify: Notify) -> bool {
+        let mut watch = Watch {
+            outputs: HashSet::new(),
+            ready: false,
+            notify,
+        };
+        let (conn, mut queue) = match subscribe(&mut watch) {
+            Ok(subscribed) => subscribed,
+            Err(e) => {
+                warn!("Could not watch wayland outputs: {}", e);
+                return false;
+            }
+        };
+        watch.ready = true;
+        thread::spawn(move || {
+            //keep the connection alive as long as the thread
+            let _conn = conn;
+            loop {
+                if let Err(e) = queue.blocking_dispatch(&mut watch) {
+                    warn!("Lost wayland output notifications: {}", e);
+                    break;
+                }
+            }
+        });
+        true
+    }
+}
+
+///State of the thread watching for outputs
+struct Watch {
+    outputs: HashSet<u32>,
+    ///false while the outputs that already exist are announced
+    ready: bool,
+    notify: Notify,
+}
+
+fn subscribe(watch: &mut Watch) -> Result<(Connection, EventQueue<Watch>)> {
+    let conn = Connection::connect_to_env()?;
+    let mut queue = conn.new_event_queue();
+    conn.display().get_registry(&queue.handle(), ());
+    queue.roundtrip(watch)?;
+    Ok((conn, queue))
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for Watch {
+    fn event(
+        watch: &mut Self,
+        _: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_registry::Event::Global { name, interface, .. } if interface == wl_output::WlOutput::interface().name => {
+                watch.outputs.insert(name);
+                if watch.ready {
+                    (watch.notify)();
+                }
+            }
+            wl_registry::Event::GlobalRemove { name } if watch.outputs.remove(&name) => (watch.notify)(),
+            _ => {}
+        }
+    }
+}
+
+///Anonymous in-memory file to hand the gamma table to the compositor
+fn memfd(name: &str) -> Result<File> {
+    let name = CString::new(name).map_err(|e| DispError::Wayland(e.to_string()))?;
+    let fd = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for State {
+    fn event(
+        state: &mut Self,
+        registry: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_registry::Event::Global { name, interface, version } => {
+                if interface == wl_output::WlOutput::interface().name {
+                    //version 4 is the first to tell us the connector name
+                    let wl = registry.bind::<wl_output::WlOutput, _, _>(name, version.min(4), qh, name);
+                    state.outputs.push(Output {
+                        global: name,
+                        wl,
+                        name: None,
+                        control: None,
+                        gamma_size: None,
+                        failed: false,
+                        applied: (100, Gamma { r: 100, g: 100, b: 100 }),
+                    });
+                } else if interface == ZwlrGammaControlManagerV1::interface().name {
+                    state.manager = Some(registry.bind(name, 1, qh, ()));
+                }
+            }
+            wl_registry::Event::GlobalRemove { name } => {
+                state.outputs.retain(|o| o.global != name);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_output::WlOutput, u32> for State {
+    fn event(
+        state: &mut Self,
+        _: &wl_output::WlOutput,
+        event: wl_output::Event,
+        global: &u32,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let output = match state.outputs.iter_mut().find(|o| o.global == *global) {
+            Some(output) => output,
+            None => return,
+        };
+        match event {
+            wl_output::Event::Name { name } => output.name = Some(name),
+            //older compositors don't send a name, fall back to the model
+            wl_output::Event::Geometry { model, .. } if output.name.is_none() => {
+                output.name = Some(format!("{}-{}", model, global));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrGammaControlManagerV1, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &ZwlrGammaControlManagerV1,
+        _: <ZwlrGammaControlManagerV1 as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrGammaControlV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        control: &ZwlrGammaControlV1,
+        event: zwlr_gamma_control_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let output = match state.outputs.iter_mut().find(|o| o.control.as_ref() == Some(control)) {
+            Some(output) => output,
+            None => return,
+        };
+        match event {
+            zwlr_gamma_control_v1::Event::GammaSize { size } => output.gamma_size = Some(size),
+            zwlr_gamma_control_v1::Event::Failed => output.failed = true,
+            _ => {}
+        }
+    }
+}
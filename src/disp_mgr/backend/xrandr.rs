@@ -0,0 +1,110 @@
+use std::process::{Command, Output};
+use super::DisplayBackend;
+use crate::disp_mgr::disp::{Disp, Gamma};
+use crate::disp_mgr::error::{DispError, Result};
+
+///Backend that shells out to the xrandr binary for everything
+#[derive(Debug, Clone)]
+pub struct Xrandr {
+    bin: String,
+}
+
+impl Xrandr {
+    pub fn new(bin: String) -> Self {
+        Xrandr { bin }
+    }
+}
+
+impl Xrandr {
+    ///Run xrandr and fail if it can't be started or exits with an error
+    fn run(&self, args: &[&str]) -> Result<Output> {
+        let out = Command::new(&self.bin)
+            .args(args)
+            .output()
+            .map_err(|e| DispError::Command(format!("could not run {}: {}", self.bin, e)))?;
+        if !out.status.success() {
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            return Err(DispError::Command(format!(
+                "{} {} exited with {}: {}",
+                self.bin,
+                args.join(" "),
+                out.status,
+                stderr.trim()
+            )));
+        }
+        Ok(out)
+    }
+}
+
+impl Default for Xrandr {
+    fn default() -> Self {
+        Xrandr::new("/bin/xrandr".to_string())
+    }
+}
+
+impl DisplayBackend for Xrandr {
+    fn name(&self) -> &'static str {
+        "xrandr"
+    }
+
+    fn outputs(&mut self) -> Result<Vec<String>> {
+        let out = self.run(&[])?;
+
+        //the name is the first word of every line that says " connected "
+        let names = String::from_utf8_lossy(&out.stdout)
+            .lines()
+            .filter(|l| l.contains(" connected "))
+            .filter_map(|l| l.split_whitespace().next())
+            .map(str::to_string)
+            .collect();
+        Ok(names)
+    }
+
+    fn read_state(&mut self, output: &str) -> Result<(u8, Gamma)> {
+        let out = self.run(&["--verbose"])?;
+        parse_verbose(&String::from_utf8_lossy(&out.stdout), output)
+    }
+
+    fn apply(&mut self, disp: &Disp) -> Result<()> {
+        self.run(&[
+            "--output",
+            &disp.name,
+            "--brightness",
+            &((disp.brightness as f32) / 100.0).to_string(),
+            "--gamma",
+            &disp.gamma.to_gamma_string(),
+        ])?;
+        Ok(())
+    }
+}
+
+///Find the Brightness and Gamma lines of an output's block in `xrandr --verbose` output.
+///A block starts with an unindented line beginning with the output name and runs until the next
+///unindented line.
+pub fn parse_verbose(verbose: &str, output: &str) -> Result<(u8, Gamma)> {
+    let header = format!("{} ", output);
+    let mut block = verbose
+        .lines()
+        .skip_while(|l| !l.starts_with(&header));
+    if block.next().is_none() {
+        return Err(DispError::NoSuchOutput(output.to_string()));
+    }
+
+    let mut brightness = None;
+    let mut gamma = None;
+    for line in block.take_while(|l| l.starts_with(char::is_whitespace)) {
+        let line = line.trim();
+        if let Some(val) = line.strip_prefix("Brightness:") {
+            let val = val.trim().parse::<f32>()
+                .map_err(|e| DispError::Parse(format!("invalid brightness '{}': {}", val.trim(), e)))?;
+            brightness = Some((val * 100.0).round().clamp(1.0, 100.0) as u8);
+        } else if let Some(val) = line.strip_prefix("Gamma:") {
+            gamma = Some(Gamma::from_gamma_string(val)?);
+        }
+    }
+
+    match (brightness, gamma) {
+        (Some(brightness), Some(gamma)) => Ok((brightness, gamma)),
+        _ => Err(DispError::Parse(format!("no brightness or gamma reported for output {}", output))),
+    }
+}
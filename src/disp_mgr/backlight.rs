@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use super::error::{DispError, Result};
+
+///Hardware backlight control through the kernel's backlight class, usually /sys/class/backlight.
+///Every device there has a `brightness` and a `max_brightness` file. Devices are matched to outputs
+///through the drm connector their `device` link points at (eg card0-eDP-1).
+#[derive(Debug, Clone)]
+pub struct Backlight {
+    root: PathBuf,
+    devices: HashMap<String, PathBuf>,
+}
+
+impl Backlight {
+    ///Create a Backlight that looks for devices under root
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Backlight {
+            root: root.into(),
+            devices: HashMap::new(),
+        }
+    }
+
+    ///Match the devices under root against outputs. Any device that isn't linked to a connector
+    ///(eg acpi_video0) is given to the internal panel if it didn't get one already.
+    pub fn scan(&mut self, outputs: &[String]) {
+        self.devices.clear();
+        //no backlight class at all is normal on desktops
+        let entries = match fs::read_dir(&self.root) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        let mut unlinked = Vec::new();
+        for dir in entries.flatten().map(|e| e.path()) {
+            let connector = fs::read_link(dir.join("device"))
+                .ok()
+                .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()));
+            match connector.and_then(|c| outputs.iter().find(|o| same_connector(&c, o))) {
+                Some(output) => {
+                    self.devices.insert(output.clone(), dir);
+                }
+                None => unlinked.push(dir),
+            }
+        }
+
+        //prefer firmware over platform over raw interfaces, the same order systemd uses
+        unlinked.sort_by_key(|dir| (type_rank(dir), dir.clone()));
+        let panel = outputs
+            .iter()
+            .find(|o| is_internal(o) && !self.devices.contains_key(*o));
+        if let (Some(panel), Some(dir)) = (panel, unlinked.into_iter().next()) {
+            self.devices.insert(panel.clone(), dir);
+        }
+    }
+
+    ///Check if an output has a backlight device
+    pub fn has(&self, output: &str) -> bool {
+        self.devices.contains_key(output)
+    }
+
+    ///Read an output's backlight in percent. None if it doesn't have one
+    pub fn read(&self, output: &str) -> Result<Option<u8>> {
+        let dir = match self.devices.get(output) {
+            Some(dir) => dir,
+            None => return Ok(None),
+        };
+        let max = read_number(&dir.join("max_brightness"))?;
+        let cur = read_number(&dir.join("brightness"))?;
+        if max == 0 {
+            return Err(DispError::Backlight(format!("{} has a max_brightness of 0", dir.display())));
+        }
+        let pct = ((cur as f64) * 100.0 / (max as f64)).round();
+        Ok(Some(pct.clamp(0.0, 100.0) as u8))
+    }
+
+    ///Set an output's backlight in percent
+    pub fn write(&self, output: &str, pct: u8) -> Result<()> {
+        let dir = self
+            .devices
+            .get(output)
+            .ok_or_else(|| DispError::Unsupported(format!("output {} has no backlight", output)))?;
+        let max = read_number(&dir.join("max_brightness"))?;
+        let raw = ((pct.min(100) as f64) * (max as f64) / 100.0).round() as u64;
+        let path = dir.join("brightness");
+        fs::write(&path, raw.to_string())
+            .map_err(|e| DispError::Backlight(format!("could not write {}: {}", path.display(), e)))
+    }
+}
+
+impl Default for Backlight {
+    fn default() -> Self {
+        Backlight::new("/sys/class/backlight")
+    }
+}
+
+fn read_number(path: &Path) -> Result<u64> {
+    let s = fs::read_to_string(path)
+        .map_err(|e| DispError::Backlight(format!("could not read {}: {}", path.display(), e)))?;
+    s.trim()
+        .parse()
+        .map_err(|e| DispError::Parse(format!("invalid number in {}: {}", path.display(), e)))
+}
+
+fn type_rank(dir: &Path) -> u8 {
+    match fs::read_to_string(dir.join("type")).unwrap_or_default().trim() {
+        "firmware" => 0,
+        "platform" => 1,
+        "raw" => 2,
+        _ => 3,
+    }
+}
+
+///Compare a drm connector like card0-eDP-1 to an output name like eDP-1 or eDP1.
+///X drivers don't agree on whether there is a dash before the number
+pub(crate) fn same_connector(connector: &str, output: &str) -> bool {
+    let connector = match connector.split_once('-') {
+        Some((card, rest)) if card.starts_with("card") => rest,
+        _ => connector,
+    };
+    let normalize = |s: &str| s.replace('-', "").to_lowercase();
+    normalize(connector) == normalize(output)
+}
+
+///Outputs that are built in panels and usually own the backlight
+fn is_internal(output: &str) -> bool {
+    let output = output.to_lowercase();
+    ["edp", "lvds", "dsi"].iter().any(|p| output.starts_with(p))
+}
@@ -0,0 +1,64 @@
+///Pull the vcp section out of an MCCS capabilities string and parse it.
+///A capabilities string looks like
+///`(prot(monitor)type(lcd)model(X)cmds(01 02 03 0C F3)vcp(02 10 12 14(05 08 0B) 60(0F 11 12))mccs_ver(2.1))`
+///and the vcp section lists supported codes in hex, each optionally followed by the values it
+///accepts in parentheses.
+pub fn parse_vcp(caps: &str) -> Vec<(u8, Vec<u16>)> {
+    let section = match find_section(caps, "vcp") {
+        Some(section) => section,
+        None => return Vec::new(),
+    };
+
+    let mut codes: Vec<(u8, Vec<u16>)> = Vec::new();
+    let mut rest = section;
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c == '(' {
+            //values of the code before it
+            let end = rest.find(')').unwrap_or(rest.len());
+            let values = rest[1..end]
+                .split_whitespace()
+                .filter_map(|v| u16::from_str_radix(v, 16).ok())
+                .collect();
+            if let Some(last) = codes.last_mut() {
+                last.1 = values;
+            }
+            rest = rest.get(end + 1..).unwrap_or("");
+        } else {
+            //some monitors don't put spaces between codes, so take at most two hex digits
+            let len = rest
+                .char_indices()
+                .take_while(|(i, c)| *i < 2 && c.is_ascii_hexdigit())
+                .count();
+            if len == 0 {
+                rest = &rest[c.len_utf8()..];
+                continue;
+            }
+            if let Ok(code) = u8::from_str_radix(&rest[..len], 16) {
+                codes.push((code, Vec::new()));
+            }
+            rest = &rest[len..];
+        }
+    }
+    codes
+}
+
+///Find the contents of a top level `name(...)` section, respecting nested parentheses
+fn find_section<'a>(caps: &'a str, name: &str) -> Option<&'a str> {
+    let start = caps.find(&format!("{}(", name))? + name.len() + 1;
+    let mut depth = 1;
+    for (i, c) in caps[start..].char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&caps[start..start + i]);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
@@ -0,0 +1,131 @@
+///How a VCP feature is adjusted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VcpKind {
+    ///Any value from 0 to the max the monitor reports, shown as a gauge
+    Continuous,
+    ///One of a list of values, shown as a pick-list
+    NonContinuous,
+}
+
+///A VCP feature tuib knows how to show
+#[derive(Debug)]
+pub struct VcpInfo {
+    pub code: u8,
+    pub name: &'static str,
+    pub kind: VcpKind,
+}
+
+///Features shown in the monitor panel. Luminance (0x10) is left out since it has its own gauge
+pub const KNOWN: &[VcpInfo] = &[
+    VcpInfo { code: 0x12, name: "Contrast", kind: VcpKind::Continuous },
+    VcpInfo { code: 0x16, name: "Red Gain", kind: VcpKind::Continuous },
+    VcpInfo { code: 0x18, name: "Green Gain", kind: VcpKind::Continuous },
+    VcpInfo { code: 0x1A, name: "Blue Gain", kind: VcpKind::Continuous },
+    VcpInfo { code: 0x14, name: "Color Preset", kind: VcpKind::NonContinuous },
+    VcpInfo { code: 0x60, name: "Input Source", kind: VcpKind::NonContinuous },
+];
+
+///Look up a known feature
+pub fn info(code: u8) -> Option<&'static VcpInfo> {
+    KNOWN.iter().find(|i| i.code == code)
+}
+
+///Human name of a value of a non continuous feature, as defined by MCCS 2.2
+pub fn value_name(code: u8, value: u16) -> Option<&'static str> {
+    let name = match (code, value) {
+        (0x14, 0x01) => "sRGB",
+        (0x14, 0x02) => "Native",
+        (0x14, 0x03) => "4000K",
+        (0x14, 0x04) => "5000K",
+        (0x14, 0x05) => "6500K",
+        (0x14, 0x06) => "7500K",
+        (0x14, 0x07) => "8200K",
+        (0x14, 0x08) => "9300K",
+        (0x14, 0x09) => "10000K",
+        (0x14, 0x0A) => "11500K",
+        (0x14, 0x0B) => "User 1",
+        (0x14, 0x0C) => "User 2",
+        (0x14, 0x0D) => "User 3",
+        (0x60, 0x01) => "VGA-1",
+        (0x60, 0x02) => "VGA-2",
+        (0x60, 0x03) => "DVI-1",
+        (0x60, 0x04) => "DVI-2",
+        (0x60, 0x05) => "Composite-1",
+        (0x60, 0x06) => "Composite-2",
+        (0x60, 0x07) => "S-Video-1",
+        (0x60, 0x08) => "S-Video-2",
+        (0x60, 0x09) => "Tuner-1",
+        (0x60, 0x0A) => "Tuner-2",
+        (0x60, 0x0B) => "Tuner-3",
+        (0x60, 0x0C) => "Component-1",
+        (0x60, 0x0D) => "Component-2",
+        (0x60, 0x0E) => "Component-3",
+        (0x60, 0x0F) => "DisplayPort-1",
+        (0x60, 0x10) => "DisplayPort-2",
+        (0x60, 0x11) => "HDMI-1",
+        (0x60, 0x12) => "HDMI-2",
+        _ => return None,
+    };
+    Some(name)
+}
+
+///State of a VCP feature of a monitor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VcpFeature {
+    pub code: u8,
+    pub cur: u16,
+    pub max: u16,
+    ///Values a non continuous feature accepts. Empty for continuous features
+    pub choices: Vec<u16>,
+}
+
+impl VcpFeature {
+    pub fn name(&self) -> &'static str {
+        info(self.code).map(|i| i.name).unwrap_or("Unknown")
+    }
+
+    ///Check if this feature is a pick-list rather than a gauge
+    pub fn is_list(&self) -> bool {
+        !self.choices.is_empty()
+    }
+
+    ///Name of the current value, for pick-lists
+    pub fn value_name(&self) -> String {
+        value_name(self.code, self.cur)
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("0x{:02x}", self.cur))
+    }
+
+    ///Current value as a percentage of max
+    pub fn pct(&self) -> u8 {
+        if self.max == 0 {
+            return 0;
+        }
+        ((self.cur as f64) * 100.0 / (self.max as f64)).round().clamp(0.0, 100.0) as u8
+    }
+
+    ///Set a continuous feature to a percentage of its max. Does nothing for pick-lists
+    pub fn set_pct(&mut self, pct: u8) {
+        if !self.is_list() {
+            self.cur = ((pct.min(100) as f64) * (self.max as f64) / 100.0).round() as u16;
+        }
+    }
+
+    ///Move to the next (or previous) value. Continuous features step by one, pick-lists cycle
+    pub fn step(&mut self, forward: bool) {
+        if self.is_list() {
+            let len = self.choices.len();
+            let i = self.choices.iter().position(|c| *c == self.cur);
+            let i = match (i, forward) {
+                (Some(i), true) => (i + 1) % len,
+                (Some(i), false) => (i + len - 1) % len,
+                (None, _) => 0,
+            };
+            self.cur = self.choices[i];
+        } else if forward {
+            self.cur = self.cur.saturating_add(1).min(self.max);
+        } else {
+            self.cur = self.cur.saturating_sub(1);
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use super::error::{DispError, Result};
+use log::debug;
+use super::backlight::same_connector;
+
+pub mod caps;
+pub mod features;
+pub mod protocol;
+pub use features::{VcpFeature, VcpKind};
+pub use protocol::{DdcCi, I2c, LinuxI2c, VcpValue};
+
+///VCP code of a monitor's luminance, ie its hardware brightness
+pub const VCP_LUMINANCE: u8 = 0x10;
+
+///A DDC/CI capable monitor
+pub type Monitor = DdcCi<Box<dyn I2c + Send>>;
+
+struct Entry {
+    monitor: Monitor,
+    luminance: VcpValue,
+    ///known features the monitor listed in its capabilities
+    features: Vec<VcpFeature>,
+}
+
+///Hardware brightness and other settings of external monitors over DDC/CI. Outputs are matched to i2c buses through
+///their drm connector, eg /sys/class/drm/card0-DP-1/ddc -> i2c-5 -> /dev/i2c-5
+pub struct Ddc {
+    drm_root: PathBuf,
+    dev_root: PathBuf,
+    monitors: HashMap<String, Entry>,
+}
+
+impl Ddc {
+    ///Create a Ddc that finds connectors under drm_root and i2c devices under dev_root
+    pub fn new(drm_root: impl Into<PathBuf>, dev_root: impl Into<PathBuf>) -> Self {
+        Ddc {
+            drm_root: drm_root.into(),
+            dev_root: dev_root.into(),
+            monitors: HashMap::new(),
+        }
+    }
+
+    ///Create a Ddc that never finds a monitor, for when waiting on i2c buses isn't worth it
+    pub fn disabled() -> Self {
+        Ddc::new(PathBuf::new(), PathBuf::new())
+    }
+
+    ///Attach a monitor to an output by hand, eg a fake i2c device. Fails if it doesn't report a luminance
+    pub fn insert(&mut self, output: String, mut monitor: Monitor) -> Result<()> {
+        let luminance = monitor.get_vcp(VCP_LUMINANCE)?;
+        let features = discover(&output, &mut monitor);
+        self.monitors.insert(output, Entry { monitor, luminance, features });
+        Ok(())
+    }
+
+    ///Find a DDC/CI monitor for every output that doesn't have one yet, and forget the ones for
+    ///outputs that are gone. Monitors that don't answer are skipped
+    pub fn scan(&mut self, outputs: &[String]) {
+        self.monitors.retain(|o, _| outputs.contains(o));
+        let entries = match fs::read_dir(&self.drm_root) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        for dir in entries.flatten().map(|e| e.path()) {
+            let connector = match dir.file_name() {
+                Some(name) => name.to_string_lossy().to_string(),
+                None => continue,
+            };
+            let output = match outputs.iter().find(|o| same_connector(&connector, o)) {
+                Some(output) if !self.monitors.contains_key(output) => output.clone(),
+                _ => continue,
+            };
+            let bus = match find_bus(&dir) {
+                Some(bus) => bus,
+                None => continue,
+            };
+            let dev = self.dev_root.join(&bus);
+            let monitor = LinuxI2c::open(&dev)
+                .map_err(|e| DispError::Ddc(format!("could not open {}: {}", dev.display(), e)))
+                .and_then(|i2c| self.insert(output.clone(), DdcCi::new(Box::new(i2c))));
+            if let Err(e) = monitor {
+                debug!("No DDC/CI for {} on {}: {}", output, bus, e);
+            }
+        }
+    }
+
+    ///Check if an output has a DDC/CI monitor
+    pub fn has(&self, output: &str) -> bool {
+        self.monitors.contains_key(output)
+    }
+
+    ///Read an output's luminance in percent. None if it has no DDC/CI monitor
+    pub fn read(&mut self, output: &str) -> Result<Option<u8>> {
+        let entry = match self.monitors.get_mut(output) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        entry.luminance = entry.monitor.get_vcp(VCP_LUMINANCE)?;
+        Ok(Some(entry.luminance.pct()))
+    }
+
+    ///Set an output's luminance in percent
+    pub fn write(&mut self, output: &str, pct: u8) -> Result<()> {
+        let entry = self.entry(output)?;
+        entry.luminance.cur = entry.luminance.raw(pct);
+        entry.monitor.set_vcp(VCP_LUMINANCE, entry.luminance.cur)
+    }
+
+    ///Read the current value of every known feature of an output's monitor
+    pub fn read_features(&mut self, output: &str) -> Result<Vec<VcpFeature>> {
+        let entry = match self.monitors.get_mut(output) {
+            Some(entry) => entry,
+            None => return Ok(Vec::new()),
+        };
+        for feature in entry.features.iter_mut() {
+            feature.cur = entry.monitor.get_vcp(feature.code)?.cur;
+        }
+        Ok(entry.features.clone())
+    }
+
+    ///Write a feature of an output's monitor
+    pub fn write_feature(&mut self, output: &str, feature: &VcpFeature) -> Result<()> {
+        let entry = self.entry(output)?;
+        entry.monitor.set_vcp(feature.code, feature.cur)?;
+        if let Some(f) = entry.features.iter_mut().find(|f| f.code == feature.code) {
+            f.cur = feature.cur;
+        }
+        Ok(())
+    }
+
+    fn entry(&mut self, output: &str) -> Result<&mut Entry> {
+        self.monitors
+            .get_mut(output)
+            .ok_or_else(|| DispError::Unsupported(format!("output {} has no DDC/CI monitor", output)))
+    }
+}
+
+impl Default for Ddc {
+    fn default() -> Self {
+        Ddc::new("/sys/class/drm", "/dev")
+    }
+}
+
+///Find which known features a monitor supports from its capabilities string. A monitor that
+///won't give one only gets the luminance gauge
+fn discover(output: &str, monitor: &mut Monitor) -> Vec<VcpFeature> {
+    let caps = match monitor.capabilities() {
+        Ok(caps) => caps,
+        Err(e) => {
+            debug!("No capabilities from {}: {}", output, e);
+            return Vec::new();
+        }
+    };
+
+    let mut found = Vec::new();
+    for (code, values) in caps::parse_vcp(&caps) {
+        let info = match features::info(code) {
+            Some(info) => info,
+            None => continue,
+        };
+        let val = match monitor.get_vcp(code) {
+            Ok(val) => val,
+            Err(e) => {
+                debug!("{} lists vcp 0x{:02x} but won't report it: {}", output, code, e);
+                continue;
+            }
+        };
+        let choices = match info.kind {
+            VcpKind::Continuous => Vec::new(),
+            //a pick-list without anything to pick from is useless
+            VcpKind::NonContinuous if values.is_empty() => continue,
+            VcpKind::NonContinuous => values,
+        };
+        found.push(VcpFeature { code, cur: val.cur, max: val.max, choices });
+    }
+    //show them in a stable order no matter how the monitor lists them
+    found.sort_by_key(|f| features::KNOWN.iter().position(|i| i.code == f.code));
+    found
+}
+
+///Find the i2c bus of a drm connector. Most drivers link it as `ddc`, DisplayPort connectors
+///may instead have their aux channel bus as an i2c-N child
+fn find_bus(connector: &Path) -> Option<String> {
+    let name = |p: &Path| p.file_name().map(|n| n.to_string_lossy().to_string());
+    if let Some(bus) = fs::read_link(connector.join("ddc")).ok().and_then(|p| name(&p)) {
+        return Some(bus);
+    }
+    fs::read_dir(connector)
+        .ok()?
+        .flatten()
+        .filter_map(|e| name(&e.path()))
+        .find(|n| n.starts_with("i2c-"))
+}
@@ -0,0 +1,242 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use crate::disp_mgr::error::{DispError, Result};
+
+///i2c address monitors answer DDC/CI on
+pub const DDC_ADDR: u16 = 0x37;
+///"source address" of the host in every message we send
+const HOST_ADDR: u8 = 0x51;
+///destination address the checksum of outgoing messages starts from
+const DEST_ADDR: u8 = 0x6E;
+///virtual host address the checksum of replies starts from
+const REPLY_ADDR: u8 = 0x50;
+
+const GET_VCP: u8 = 0x01;
+const GET_VCP_REPLY: u8 = 0x02;
+const SET_VCP: u8 = 0x03;
+const CAPABILITIES: u8 = 0xF3;
+const CAPABILITIES_REPLY: u8 = 0xE3;
+///most capability bytes a monitor sends per reply
+const CAPABILITIES_CHUNK: usize = 32;
+
+///Something that can talk to an i2c bus. Implemented for /dev/i2c-* by LinuxI2c, and easy to fake
+pub trait I2c {
+    fn write(&mut self, addr: u16, data: &[u8]) -> io::Result<()>;
+    fn read(&mut self, addr: u16, buf: &mut [u8]) -> io::Result<()>;
+}
+
+impl<T: I2c + ?Sized> I2c for Box<T> {
+    fn write(&mut self, addr: u16, data: &[u8]) -> io::Result<()> {
+        (**self).write(addr, data)
+    }
+
+    fn read(&mut self, addr: u16, buf: &mut [u8]) -> io::Result<()> {
+        (**self).read(addr, buf)
+    }
+}
+
+///An i2c bus exposed by the i2c-dev kernel module
+#[derive(Debug)]
+pub struct LinuxI2c {
+    file: File,
+}
+
+impl LinuxI2c {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Ok(LinuxI2c { file })
+    }
+
+    fn set_slave(&self, addr: u16) -> io::Result<()> {
+        //I2C_SLAVE from linux/i2c-dev.h
+        const I2C_SLAVE: libc::c_ulong = 0x0703;
+        let ret = unsafe { libc::ioctl(self.file.as_raw_fd(), I2C_SLAVE as _, addr as libc::c_ulong) };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl I2c for LinuxI2c {
+    fn write(&mut self, addr: u16, data: &[u8]) -> io::Result<()> {
+        self.set_slave(addr)?;
+        self.file.write_all(data)
+    }
+
+    fn read(&mut self, addr: u16, buf: &mut [u8]) -> io::Result<()> {
+        self.set_slave(addr)?;
+        self.file.read_exact(buf)
+    }
+}
+
+///Current and maximum value of a VCP feature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VcpValue {
+    pub cur: u16,
+    pub max: u16,
+}
+
+impl VcpValue {
+    ///Current value as a percentage of max
+    pub fn pct(&self) -> u8 {
+        if self.max == 0 {
+            return 0;
+        }
+        ((self.cur as f64) * 100.0 / (self.max as f64)).round().clamp(0.0, 100.0) as u8
+    }
+
+    ///Raw value for a percentage of max
+    pub fn raw(&self, pct: u8) -> u16 {
+        ((pct.min(100) as f64) * (self.max as f64) / 100.0).round() as u16
+    }
+}
+
+///DDC/CI (VESA MCCS over i2c) connection to a single monitor
+#[derive(Debug)]
+pub struct DdcCi<D: I2c> {
+    dev: D,
+    ///how long the monitor needs between a request and reading the reply
+    delay: Duration,
+    retries: usize,
+}
+
+impl<D: I2c> DdcCi<D> {
+    pub fn new(dev: D) -> Self {
+        DdcCi {
+            dev,
+            delay: Duration::from_millis(50),
+            retries: 3,
+        }
+    }
+
+    ///Change the delay between request and reply. A fake device doesn't need one
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    ///Read a VCP feature. Retried a few times since monitors tend to drop requests
+    pub fn get_vcp(&mut self, code: u8) -> Result<VcpValue> {
+        let mut last_err = DispError::Ddc("no attempts made".to_string());
+        for _ in 0..self.retries {
+            match self.try_get_vcp(code) {
+                Ok(val) => return Ok(val),
+                Err(e) => last_err = e,
+            }
+        }
+        Err(last_err)
+    }
+
+    ///Write a VCP feature
+    pub fn set_vcp(&mut self, code: u8, val: u16) -> Result<()> {
+        let [hi, lo] = val.to_be_bytes();
+        self.send(&[SET_VCP, code, hi, lo])?;
+        thread::sleep(self.delay);
+        Ok(())
+    }
+
+    ///Read the capabilities string. It is sent in chunks, each request asks for the next offset
+    ///until the monitor replies with an empty chunk
+    pub fn capabilities(&mut self) -> Result<String> {
+        let mut caps = Vec::new();
+        loop {
+            let chunk = self.capabilities_chunk(caps.len() as u16)?;
+            if chunk.is_empty() {
+                break;
+            }
+            caps.extend_from_slice(&chunk);
+            //caps strings are a few hundred bytes, anything huge is a confused monitor
+            if caps.len() > 4096 {
+                return Err(DispError::Ddc("capabilities string is too long".to_string()));
+            }
+        }
+        //some monitors null terminate the string
+        while caps.last() == Some(&0) {
+            caps.pop();
+        }
+        Ok(String::from_utf8_lossy(&caps).to_string())
+    }
+
+    fn capabilities_chunk(&mut self, offset: u16) -> Result<Vec<u8>> {
+        let mut last_err = DispError::Ddc("no attempts made".to_string());
+        for _ in 0..self.retries {
+            let [hi, lo] = offset.to_be_bytes();
+            self.send(&[CAPABILITIES, hi, lo])?;
+            thread::sleep(self.delay);
+
+            let mut reply = [0u8; CAPABILITIES_CHUNK + 6];
+            self.dev.read(DDC_ADDR, &mut reply)?;
+            match check_reply(&reply) {
+                Ok(payload) if payload.len() >= 3 && payload[0] == CAPABILITIES_REPLY => {
+                    if u16::from_be_bytes([payload[1], payload[2]]) != offset {
+                        last_err = DispError::Ddc("capabilities reply for the wrong offset".to_string());
+                        continue;
+                    }
+                    return Ok(payload[3..].to_vec());
+                }
+                Ok(payload) => last_err = DispError::Ddc(format!("unexpected capabilities reply: {:02x?}", payload)),
+                Err(e) => last_err = e,
+            }
+        }
+        Err(last_err)
+    }
+
+    fn try_get_vcp(&mut self, code: u8) -> Result<VcpValue> {
+        self.send(&[GET_VCP, code])?;
+        thread::sleep(self.delay);
+
+        let mut reply = [0u8; 11];
+        self.dev.read(DDC_ADDR, &mut reply)?;
+        let payload = check_reply(&reply)?;
+        if payload.len() != 8 || payload[0] != GET_VCP_REPLY {
+            return Err(DispError::Ddc(format!("unexpected reply to get vcp 0x{:02x}: {:02x?}", code, payload)));
+        }
+        if payload[1] != 0 {
+            return Err(DispError::Unsupported(format!("vcp 0x{:02x} is not supported", code)));
+        }
+        if payload[2] != code {
+            return Err(DispError::Ddc(format!("asked for vcp 0x{:02x}, got 0x{:02x}", code, payload[2])));
+        }
+        Ok(VcpValue {
+            max: u16::from_be_bytes([payload[4], payload[5]]),
+            cur: u16::from_be_bytes([payload[6], payload[7]]),
+        })
+    }
+
+    ///Wrap data in the DDC/CI envelope (source, length, data, checksum) and write it
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        let mut msg = Vec::with_capacity(data.len() + 3);
+        msg.push(HOST_ADDR);
+        msg.push(0x80 | data.len() as u8);
+        msg.extend_from_slice(data);
+        msg.push(checksum(DEST_ADDR, &msg));
+        self.dev.write(DDC_ADDR, &msg)?;
+        Ok(())
+    }
+}
+
+///Validate the envelope of a reply and return the payload
+fn check_reply(reply: &[u8]) -> Result<&[u8]> {
+    if reply.len() < 3 || reply[0] != DEST_ADDR || reply[1] & 0x80 == 0 {
+        return Err(DispError::Ddc(format!("malformed ddc reply: {:02x?}", reply)));
+    }
+    let len = (reply[1] & 0x7f) as usize;
+    if len + 3 > reply.len() {
+        return Err(DispError::Ddc(format!("ddc reply length {} does not fit in {} bytes", len, reply.len())));
+    }
+    let expected = checksum(REPLY_ADDR, &reply[..len + 2]);
+    if reply[len + 2] != expected {
+        return Err(DispError::Ddc(format!("bad ddc checksum: got 0x{:02x}, expected 0x{:02x}", reply[len + 2], expected)));
+    }
+    Ok(&reply[2..len + 2])
+}
+
+///DDC/CI checksum: xor of the starting address and every byte
+pub fn checksum(start: u8, bytes: &[u8]) -> u8 {
+    bytes.iter().fold(start, |acc, b| acc ^ b)
+}
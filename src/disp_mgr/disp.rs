@@ -1,36 +1,63 @@
-use std::process::Command;
-
-
-#[derive(Debug, Clone, Copy, PartialEq)]
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use super::error::{DispError, Result};
+use super::ddc::VcpFeature;
+use super::temperature;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum DispProp {
     Brightness,
+    Backlight,
+    Luminance,
+    ///Color temperature in Kelvin. Drives R, G and B
+    Temperature,
+    #[serde(rename = "red")]
     R,
+    #[serde(rename = "green")]
     G,
+    #[serde(rename = "blue")]
     B,
+    ///A DDC/CI feature of the monitor, by VCP code
+    Vcp(u8),
 }
 
 impl DispProp {
-    pub fn next(&mut self) {
-        *self = match self {
-            DispProp::Brightness => DispProp::R,
-            DispProp::R          => DispProp::G,
-            DispProp::G          => DispProp::B,
-            DispProp::B          => DispProp::Brightness,
-        };
+    ///Move to the next prop in props, wrapping around. props is what the focused Disp supports
+    pub fn next(&mut self, props: &[DispProp]) {
+        if let Some(i) = props.iter().position(|p| p == self) {
+            *self = props[(i + 1) % props.len()];
+        } else if let Some(first) = props.first() {
+            *self = *first;
+        }
     }
 
-    pub fn prev(&mut self) {
-        *self = match self {
-            DispProp::Brightness => DispProp::B,
-            DispProp::R          => DispProp::Brightness,
-            DispProp::G          => DispProp::R,
-            DispProp::B          => DispProp::G,
-        };
+    ///Move to the previous prop in props, wrapping around
+    pub fn prev(&mut self, props: &[DispProp]) {
+        if let Some(i) = props.iter().position(|p| p == self) {
+            *self = props[(i + props.len() - 1) % props.len()];
+        } else if let Some(first) = props.first() {
+            *self = *first;
+        }
+    }
+}
+
+impl fmt::Display for DispProp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DispProp::Brightness => write!(f, "Brightness"),
+            DispProp::Backlight => write!(f, "Backlight"),
+            DispProp::Luminance => write!(f, "Luminance"),
+            DispProp::Temperature => write!(f, "Temperature"),
+            DispProp::R => write!(f, "Red"),
+            DispProp::G => write!(f, "Green"),
+            DispProp::B => write!(f, "Blue"),
+            DispProp::Vcp(code) => write!(f, "VCP {:#04x}", code),
+        }
     }
 }
-//implement next and prev for this enum to use in ui
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Gamma {
     pub r: u8,
     pub g: u8,
@@ -38,34 +65,44 @@ pub struct Gamma {
 }
 
 impl Gamma {
-    pub fn to_gamma_string(self, prop: &DispProp, val: u8) -> String {
-        let mut fr = ((self.r as f32) / 100.0).to_string();
-        let mut fg = ((self.g as f32) / 100.0).to_string();
-        let mut fb = ((self.b as f32) / 100.0).to_string();
-        match prop {
-            DispProp::R => fr = ((val as f32) / 100.0).to_string(),
-            DispProp::G => fg = ((val as f32) / 100.0).to_string(),
-            DispProp::B => fb = ((val as f32) / 100.0).to_string(),
-            _ => {}
-        };
+    ///Format as the r:g:b string xrandr --gamma expects
+    pub fn to_gamma_string(self) -> String {
+        let fr = ((self.r as f32) / 100.0).to_string();
+        let fg = ((self.g as f32) / 100.0).to_string();
+        let fb = ((self.b as f32) / 100.0).to_string();
         fr + ":" + &fg + ":" + &fb
     }
 
-    // TODO: this will take a gamma string from xrandr and parse it as relative percentages.
-    // it won't be exact, but it will be close enough
-    // this is for when you update tuib to read the gamma values from xrandr instead of assuming they're all 100%
-    // example gamma string: 1.3:1.7:2.5
-    // this was taken when r was 80%, green was 60%, and blue was 40%
-    // if you input '1.3:1.7:2.5' into this function, it will report 76 58 40, which is not exactly right, but close enough
-    pub fn from_gamma_string(&mut self, gamma_str: &str) {
-        let values: Vec<&str> = gamma_str.split(':').collect();
+    ///Inverse of to_gamma_string, as seen through xrandr.
+    ///to_gamma_string produces the value handed to `xrandr --gamma`, which is val / 100. xrandr turns
+    ///that into a ramp with exponent 1 / (val / 100), and `xrandr --verbose` reports that exponent, so
+    ///each channel is recovered as 100 / exponent.
+    ///It won't be exact because xrandr estimates the exponent from the ramp, but it is close enough.
+    ///example: '1.3:1.7:2.5' was reported when r was 80%, g was 60% and b was 40%. this returns 77 59 40
+    pub fn from_gamma_string(gamma_str: &str) -> Result<Gamma> {
+        let values = gamma_str
+            .trim()
+            .split(':')
+            .map(|v| v.parse::<f32>())
+            .collect::<std::result::Result<Vec<f32>, _>>()
+            .map_err(|e| DispError::Parse(format!("invalid gamma string '{}': {}", gamma_str, e)))?;
         if values.len() != 3 {
-            println!("Invalid gamma string format");
+            return Err(DispError::Parse(format!("invalid gamma string '{}': expected r:g:b", gamma_str)));
         }
 
-        self.r = (100.0 / values[0].parse::<f32>().unwrap_or(1.0)) as u8;
-        self.g = (100.0 / values[1].parse::<f32>().unwrap_or(1.0)) as u8;
-        self.b = (100.0 / values[2].parse::<f32>().unwrap_or(1.0)) as u8;
+        Ok(Gamma {
+            r: Gamma::pct_from_exponent(values[0]),
+            g: Gamma::pct_from_exponent(values[1]),
+            b: Gamma::pct_from_exponent(values[2]),
+        })
+    }
+
+    ///Convert a ramp exponent (what xrandr --verbose reports) to the percentage tuib uses
+    pub fn pct_from_exponent(exponent: f32) -> u8 {
+        if !exponent.is_finite() || exponent <= 0.0 {
+            return 100;
+        }
+        (100.0 / exponent).round().clamp(1.0, 100.0) as u8
     }
 }
 
@@ -74,6 +111,14 @@ pub struct Disp {
     pub name: String,
     pub brightness: u8,
     pub gamma: Gamma,
+    ///Hardware backlight in percent. None if the output has no backlight device
+    pub backlight: Option<u8>,
+    ///Monitor luminance over DDC/CI in percent. None if the monitor doesn't speak DDC/CI
+    pub luminance: Option<u8>,
+    ///Other DDC/CI features the monitor supports, shown in their own panel
+    pub vcp: Vec<VcpFeature>,
+    ///Color temperature in Kelvin the gamma was last set from
+    pub temperature: u16,
 }
 
 impl Disp {
@@ -82,54 +127,122 @@ impl Disp {
             name,
             brightness,
             gamma,
+            backlight: None,
+            luminance: None,
+            vcp: Vec::new(),
+            temperature: temperature::NEUTRAL,
+        }
+    }
+
+    ///Get the DispProps this display supports, in the order they are shown in the ui
+    pub fn props(&self) -> Vec<DispProp> {
+        let mut props = vec![DispProp::Brightness];
+        if self.backlight.is_some() {
+            props.push(DispProp::Backlight);
+        }
+        if self.luminance.is_some() {
+            props.push(DispProp::Luminance);
+        }
+        props.extend([DispProp::Temperature, DispProp::R, DispProp::G, DispProp::B]);
+        props.extend(self.vcp.iter().map(|f| DispProp::Vcp(f.code)));
+        props
+    }
+
+    ///Get the current value of a single DispProp
+    pub fn get_value(&self, prop: DispProp) -> u8 {
+        match prop {
+            DispProp::Brightness => self.brightness,
+            DispProp::Backlight => self.backlight.unwrap_or(0),
+            DispProp::Luminance => self.luminance.unwrap_or(0),
+            DispProp::Temperature => {
+                let range = (temperature::MAX - temperature::MIN) as f64;
+                ((self.temperature.saturating_sub(temperature::MIN)) as f64 * 100.0 / range).round() as u8
+            }
+            DispProp::R => self.gamma.r,
+            DispProp::G => self.gamma.g,
+            DispProp::B => self.gamma.b,
+            DispProp::Vcp(code) => self.vcp_feature(code).map(|f| f.pct()).unwrap_or(0),
+        }
+    }
+
+    ///Get a DDC/CI feature of the monitor
+    pub fn vcp_feature(&self, code: u8) -> Option<&VcpFeature> {
+        self.vcp.iter().find(|f| f.code == code)
+    }
+
+    ///Set a DDC/CI feature of the monitor to a raw value, no further than its max
+    pub fn set_vcp(&mut self, code: u8, cur: u16) {
+        if let Some(f) = self.vcp.iter_mut().find(|f| f.code == code) {
+            f.cur = cur.min(f.max);
         }
     }
 
-    fn get_brightness(&self) -> String {
-        ((self.brightness as f32) / 100.0).to_string()
+    ///Set the color temperature and the gamma that goes with it
+    pub fn set_temperature(&mut self, kelvin: u16) {
+        self.temperature = kelvin.clamp(temperature::MIN, temperature::MAX);
+        self.gamma = temperature::to_gamma(self.temperature);
     }
 
     pub fn increment_value(&mut self, prop: DispProp) {
-        let val = match prop {
-            DispProp::Brightness => self.brightness + 1,
-            DispProp::R => self.gamma.r + 1,
-            DispProp::G => self.gamma.g + 1,
-            DispProp::B => self.gamma.b + 1,
-        };
-        self.set_value(prop, val);
+        if prop == DispProp::Temperature {
+            self.set_temperature(self.temperature.saturating_add(temperature::STEP));
+            return;
+        }
+        if let DispProp::Vcp(code) = prop {
+            if let Some(f) = self.vcp.iter_mut().find(|f| f.code == code) {
+                f.step(true);
+            }
+            return;
+        }
+        self.set_value(prop, self.get_value(prop).saturating_add(1));
     }
 
     pub fn decrement_value(&mut self, prop: DispProp) {
-        let val = match prop {
-            DispProp::Brightness => self.brightness - 1,
-            DispProp::R => self.gamma.r - 1,
-            DispProp::G => self.gamma.g - 1,
-            DispProp::B => self.gamma.b - 1,
-        };
-        self.set_value(prop, val);
+        if prop == DispProp::Temperature {
+            self.set_temperature(self.temperature.saturating_sub(temperature::STEP));
+            return;
+        }
+        if let DispProp::Vcp(code) = prop {
+            if let Some(f) = self.vcp.iter_mut().find(|f| f.code == code) {
+                f.step(false);
+            }
+            return;
+        }
+        self.set_value(prop, self.get_value(prop).saturating_sub(1));
     }
 
-    pub fn set_value(&mut self, prop: DispProp, mut val: u8) {
-        //TODO: error checking
-        val = val.clamp(1, 100);
-        let mut new_disp = Disp::new(self.name.clone(), self.brightness, self.gamma);
+    ///Set a single DispProp. Only changes the struct, DispMgr is responsible for applying it
+    pub fn set_value(&mut self, prop: DispProp, val: u8) {
+        //monitor settings like contrast can go all the way to 0, everything else stops at 1
+        if let DispProp::Vcp(code) = prop {
+            if let Some(f) = self.vcp.iter_mut().find(|f| f.code == code) {
+                f.set_pct(val);
+            }
+        }
+        let val = val.clamp(1, 100);
         match prop {
-            DispProp::R => new_disp.gamma.r = val,
-            DispProp::G => new_disp.gamma.g = val,
-            DispProp::B => new_disp.gamma.b = val,
-            DispProp::Brightness => new_disp.brightness = val,
+            DispProp::R => self.gamma.r = val,
+            DispProp::G => self.gamma.g = val,
+            DispProp::B => self.gamma.b = val,
+            DispProp::Brightness => self.brightness = val,
+            DispProp::Backlight => {
+                if let Some(backlight) = self.backlight.as_mut() {
+                    *backlight = val;
+                }
+            }
+            DispProp::Luminance => {
+                if let Some(luminance) = self.luminance.as_mut() {
+                    *luminance = val;
+                }
+            }
+            DispProp::Temperature => {
+                //val is a position along the gauge, snap it to a whole step
+                let range = (temperature::MAX - temperature::MIN) as u32;
+                let kelvin = temperature::MIN as u32 + range * val as u32 / 100;
+                let step = temperature::STEP as u32;
+                self.set_temperature((kelvin / step * step) as u16);
+            }
+            DispProp::Vcp(_) => {}
         };
-
-        let _out = Command::new("/bin/xrandr")
-            .arg("--output")
-            .arg(&new_disp.name)
-            .arg("--brightness")
-            .arg(new_disp.get_brightness())
-            .arg("--gamma")
-            .arg(new_disp.gamma.to_gamma_string(&prop, val))
-            .output()
-            .expect("failed to execute process");
-
-        *self = new_disp;
     }
 }
@@ -0,0 +1,105 @@
+use std::fmt::{self, Display};
+use std::io;
+
+///Everything that can go wrong while finding or changing displays
+#[derive(Debug)]
+pub enum DispError {
+    ///No connected output has this name
+    NoSuchOutput(String),
+    ///No display at this index
+    NoSuchIndex(usize),
+    ///The output exists but can't do what was asked, eg it has no backlight
+    Unsupported(String),
+    ///An external command couldn't be run or exited with an error
+    Command(String),
+    ///Talking to the X server failed
+    X11(String),
+    ///Talking to the wayland compositor failed
+    Wayland(String),
+    ///Reading or writing a sysfs backlight failed
+    Backlight(String),
+    ///DDC/CI communication with a monitor failed
+    Ddc(String),
+    ///Output of xrandr, sysfs or a monitor couldn't be understood
+    Parse(String),
+    ///A profile doesn't exist, or profiles.toml or state.toml couldn't be read or written
+    Profile(String),
+    ///A running daemon turned down a request, or couldn't be talked to
+    Daemon(String),
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, DispError>;
+
+impl Display for DispError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DispError::NoSuchOutput(name) => write!(f, "no display named {}", name),
+            DispError::NoSuchIndex(i) => write!(f, "no display at index {}", i),
+            DispError::Unsupported(msg) => write!(f, "unsupported: {}", msg),
+            DispError::Command(msg) => write!(f, "command failed: {}", msg),
+            DispError::X11(msg) => write!(f, "X11: {}", msg),
+            DispError::Wayland(msg) => write!(f, "wayland: {}", msg),
+            DispError::Backlight(msg) => write!(f, "backlight: {}", msg),
+            DispError::Ddc(msg) => write!(f, "DDC/CI: {}", msg),
+            DispError::Parse(msg) => write!(f, "parse error: {}", msg),
+            DispError::Profile(msg) => write!(f, "profile: {}", msg),
+            DispError::Daemon(msg) => write!(f, "daemon: {}", msg),
+            DispError::Io(e) => write!(f, "io: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DispError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DispError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DispError {
+    fn from(e: io::Error) -> Self {
+        DispError::Io(e)
+    }
+}
+
+impl From<x11rb::errors::ConnectError> for DispError {
+    fn from(e: x11rb::errors::ConnectError) -> Self {
+        DispError::X11(e.to_string())
+    }
+}
+
+impl From<x11rb::errors::ConnectionError> for DispError {
+    fn from(e: x11rb::errors::ConnectionError) -> Self {
+        DispError::X11(e.to_string())
+    }
+}
+
+impl From<x11rb::errors::ReplyError> for DispError {
+    fn from(e: x11rb::errors::ReplyError) -> Self {
+        DispError::X11(e.to_string())
+    }
+}
+
+#[cfg(feature = "wayland")]
+impl From<wayland_client::ConnectError> for DispError {
+    fn from(e: wayland_client::ConnectError) -> Self {
+        DispError::Wayland(e.to_string())
+    }
+}
+
+#[cfg(feature = "wayland")]
+impl From<wayland_client::DispatchError> for DispError {
+    fn from(e: wayland_client::DispatchError) -> Self {
+        DispError::Wayland(e.to_string())
+    }
+}
+
+#[cfg(feature = "wayland")]
+impl From<wayland_client::backend::WaylandError> for DispError {
+    fn from(e: wayland_client::backend::WaylandError) -> Self {
+        DispError::Wayland(e.to_string())
+    }
+}
@@ -0,0 +1,47 @@
+use serde::Deserialize;
+
+///Displays that move together. Members keep their calibration relative to the leader: each one is
+///set to leader * multiplier + offset whenever the leader changes
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Group {
+    pub name: String,
+    ///Output name of the display whose gauges the group tab shows
+    pub leader: String,
+    #[serde(default)]
+    pub members: Vec<Member>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Member {
+    ///Output name
+    pub output: String,
+    #[serde(default)]
+    pub offset: i16,
+    #[serde(default = "default_multiplier")]
+    pub multiplier: f64,
+}
+
+fn default_multiplier() -> f64 {
+    1.0
+}
+
+impl Member {
+    ///Value of this member when the leader is at leader
+    pub fn value(&self, leader: u8) -> u8 {
+        (leader as f64 * self.multiplier + self.offset as f64).round().clamp(1.0, 100.0) as u8
+    }
+
+    ///How the member relates to the leader, eg "HDMI-1 x0.8 -5"
+    pub fn describe(&self) -> String {
+        let mut desc = self.output.clone();
+        if self.multiplier != 1.0 {
+            desc += &format!(" x{}", self.multiplier);
+        }
+        if self.offset != 0 {
+            desc += &format!(" {:+}", self.offset);
+        }
+        desc
+    }
+}
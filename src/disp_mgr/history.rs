@@ -0,0 +1,126 @@
+use std::fmt::{self, Display};
+use serde::{Deserialize, Serialize};
+use super::disp::{Disp, DispProp};
+
+///How many entries are kept to undo
+pub const LIMIT: usize = 100;
+
+///One DispProp of one display moving from one value to another. Values are Kelvin for
+///Temperature, since its gauge is too coarse to get back to the same temperature, the raw value
+///for monitor features, which can be pick-lists or go past 100, and the value of the gauge for
+///everything else
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change {
+    ///Output name, so entries survive displays coming and going
+    pub output: String,
+    pub prop: DispProp,
+    pub from: u16,
+    pub to: u16,
+}
+
+impl Change {
+    ///Value of prop on disp, the way a Change keeps it
+    pub fn value_of(disp: &Disp, prop: DispProp) -> u16 {
+        match prop {
+            DispProp::Temperature => disp.temperature,
+            DispProp::Vcp(code) => disp.vcp_feature(code).map(|f| f.cur).unwrap_or(0),
+            _ => disp.get_value(prop) as u16,
+        }
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.prop {
+            DispProp::Temperature => write!(f, "{} {} {}K -> {}K", self.output, self.prop, self.from, self.to),
+            _ => write!(f, "{} {} {} -> {}", self.output, self.prop, self.from, self.to),
+        }
+    }
+}
+
+///Everything one input changed. Linked mode, groups, syncs and profiles change several displays
+///at once, and a temperature moves R, G and B along with it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    ///In the order props are shown, so Temperature comes before the R, G and B it drives
+    pub changes: Vec<Change>,
+}
+
+impl Display for Entry {
+    ///The first change, and how many more there are
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.changes.first() {
+            Some(first) => write!(f, "{}", first)?,
+            None => return Ok(()),
+        }
+        match self.changes.len() {
+            1 => Ok(()),
+            n => write!(f, " and {} more", n - 1),
+        }
+    }
+}
+
+///Undo and redo stacks of Entries
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct History {
+    done: Vec<Entry>,
+    undone: Vec<Entry>,
+}
+
+impl History {
+    ///Remember a new entry. Whatever was undone can't be redone after this
+    pub fn push(&mut self, entry: Entry) {
+        if entry.changes.is_empty() {
+            return;
+        }
+        self.undone.clear();
+        self.done.push(entry);
+        if self.done.len() > LIMIT {
+            self.done.remove(0);
+        }
+    }
+
+    ///Take the last entry to undo, and keep it for redo
+    pub fn undo(&mut self) -> Option<Entry> {
+        let entry = self.done.pop()?;
+        self.undone.push(entry.clone());
+        Some(entry)
+    }
+
+    ///Take the last undone entry to redo, and keep it for undo
+    pub fn redo(&mut self) -> Option<Entry> {
+        let entry = self.undone.pop()?;
+        self.done.push(entry.clone());
+        Some(entry)
+    }
+
+    ///Entries that can be undone, oldest first
+    pub fn done(&self) -> &[Entry] {
+        &self.done
+    }
+
+    ///Entries that can be redone, the next one to redo last
+    pub fn undone(&self) -> &[Entry] {
+        &self.undone
+    }
+}
+
+///Changes between two sets of values from values_of, in the order of after
+pub fn diff(before: &[(String, DispProp, u16)], after: &[(String, DispProp, u16)]) -> Entry {
+    let changes = after
+        .iter()
+        .filter_map(|(output, prop, to)| {
+            let (_, _, from) = before.iter().find(|(o, p, _)| o == output && p == prop)?;
+            (from != to).then(|| Change { output: output.clone(), prop: *prop, from: *from, to: *to })
+        })
+        .collect();
+    Entry { changes }
+}
+
+///Every value of every display, the way a Change keeps them
+pub fn values_of<'a>(disps: impl IntoIterator<Item = &'a Disp>) -> Vec<(String, DispProp, u16)> {
+    disps
+        .into_iter()
+        .flat_map(|disp| disp.props().into_iter().map(move |prop| (disp.name.clone(), prop, Change::value_of(disp, prop))))
+        .collect()
+}
@@ -1,48 +1,72 @@
+pub mod backend;
+pub mod backlight;
+pub mod ddc;
 pub mod disp;
-use disp::{ Disp, Gamma, DispProp };
+pub mod error;
+pub mod group;
+pub mod history;
+pub mod profile;
+pub mod temperature;
+use log::warn;
+use backend::DisplayBackend;
+use backlight::Backlight;
+use ddc::Ddc;
+use disp::{ Disp, DispProp };
+use error::{DispError, Result};
+use history::Entry;
+use profile::{Profile, Settings};
 
-#[derive(Debug, Clone, Default)]
-pub struct DispMgr {
+pub struct DispMgr<B: DisplayBackend> {
     pub disps: Vec::<Disp>,
+    backend: B,
+    backlight: Backlight,
+    ddc: Ddc,
 }
 
-impl DispMgr {
-    //TODO: error checking
-    pub fn new() -> Self {
-        //run bash command
-        let (_ccode, cout, _cerr) = run_script::run_script!(
-            r#"
-                /bin/xrandr | /bin/grep " connected " | /bin/awk '{ print$1 }'
-            "#
-        ).unwrap();
-
-        //split xrandr output into vector of display names
-        let mut disp_names: Vec<String> = cout.split('\n')
-            .map(str::to_string)
-            .collect::<Vec<String>>();
-        disp_names.retain(|i| !i.is_empty()); //remove empty entries (sometimes one left at the end)
-
-        //create vector of displays
-        let gamma = Gamma { r: 100, g: 100, b: 100};
-        let brightness = 100;
-        let mut disps: Vec<Disp> = Vec::new();
-        for name in disp_names {
-            disps.push(
-                Disp::new(name, brightness, gamma),
-            );
-        }
+impl<B: DisplayBackend> DispMgr<B> {
+    ///Create a DispMgr with one Disp for every output the backend reports
+    pub fn new(backend: B) -> Result<Self> {
+        DispMgr::with_hardware(backend, Backlight::default(), Ddc::default())
+    }
 
-        DispMgr {
-            disps,
-        }
+    ///Same as new, but with custom hardware controls (eg rooted somewhere other than /sys, or with
+    ///fake i2c devices)
+    pub fn with_hardware(backend: B, backlight: Backlight, ddc: Ddc) -> Result<Self> {
+        let mut disp_mgr = DispMgr {
+            disps: Vec::new(),
+            backend,
+            backlight,
+            ddc,
+        };
+        disp_mgr.reload()?;
+        Ok(disp_mgr)
     }
 
-    pub fn get_disp_by_name(&mut self, name: String) -> &mut Disp {
-        self.disps.iter_mut().find(|x| x.name == name).unwrap()
+    pub fn backend(&self) -> &B {
+        &self.backend
     }
 
-    pub fn get_disp_by_index(&self, i: usize) -> Disp {
-        self.disps[i].clone()
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    pub fn get_disp_by_name(&mut self, name: String) -> Result<&mut Disp> {
+        self.disps
+            .iter_mut()
+            .find(|x| x.name == name)
+            .ok_or(DispError::NoSuchOutput(name))
+    }
+
+    pub fn get_disp_by_index(&self, i: usize) -> Result<Disp> {
+        self.disps.get(i).cloned().ok_or(DispError::NoSuchIndex(i))
+    }
+
+    ///Index of the display of an output
+    pub fn index_of(&self, name: &str) -> Result<usize> {
+        self.disps
+            .iter()
+            .position(|d| d.name == name)
+            .ok_or_else(|| DispError::NoSuchOutput(name.to_string()))
     }
 
     pub fn get_name_list(&self) -> Vec<&str> {
@@ -53,31 +77,196 @@ impl DispMgr {
         self.disps.len()
     }
 
-    pub fn set_value_by_name(&mut self, name: String, prop: DispProp, val: u8) {
-        self.get_disp_by_name(name).set_value(prop, val);
+    pub fn set_value_by_name(&mut self, name: String, prop: DispProp, val: u8) -> Result<()> {
+        match self.disps.iter().position(|x| x.name == name) {
+            Some(index) => self.set_value_by_index(index, prop, val),
+            None => Err(DispError::NoSuchOutput(name)),
+        }
+    }
+
+    pub fn set_value_by_index(&mut self, index: usize, prop: DispProp, val: u8) -> Result<()> {
+        self.update(index, prop, |d| d.set_value(prop, val))
+    }
+
+    pub fn increment_value_by_index(&mut self, index: usize, prop: DispProp) -> Result<()> {
+        self.update(index, prop, |d| d.increment_value(prop))
+    }
+
+    pub fn decrement_value_by_index(&mut self, index: usize, prop: DispProp) -> Result<()> {
+        self.update(index, prop, |d| d.decrement_value(prop))
+    }
+
+    ///Move a DispProp by delta, in Kelvin for Temperature and percent for everything else
+    pub fn step_value_by_index(&mut self, index: usize, prop: DispProp, delta: i32) -> Result<()> {
+        self.update(index, prop, |d| match prop {
+            DispProp::Temperature => d.set_temperature((d.temperature as i32 + delta).clamp(0, u16::MAX as i32) as u16),
+            _ => d.set_value(prop, (d.get_value(prop) as i32 + delta).clamp(0, 100) as u8),
+        })
+    }
+
+    pub fn set_temperature_by_index(&mut self, index: usize, kelvin: u16) -> Result<()> {
+        self.update(index, DispProp::Temperature, |d| d.set_temperature(kelvin))
+    }
+
+    ///Give every other display the brightness, color and hardware levels of the display at index
+    pub fn sync_from_index(&mut self, index: usize) -> Result<()> {
+        let settings = Settings::from(&self.get_disp_by_index(index)?);
+        let mut result = Ok(());
+        for i in (0..self.disps.len()).filter(|i| *i != index) {
+            result = result.and(self.apply_settings(i, &settings));
+        }
+        result
     }
 
-    pub fn set_value_by_index(&mut self, index: usize, prop: DispProp, val: u8) {
-        self.disps[index].set_value(prop, val);
+    ///Set the display at index to settings. Backlight and luminance are only set if the display
+    ///has them. Values out of range are clamped
+    pub fn apply_settings(&mut self, index: usize, settings: &Settings) -> Result<()> {
+        let color = self.update(index, DispProp::Brightness, |d| {
+            d.set_value(DispProp::Brightness, settings.brightness);
+            //the saved gamma wins over the temperature's, it may have been changed after picking it
+            d.set_temperature(settings.temperature);
+            d.set_value(DispProp::R, settings.gamma[0]);
+            d.set_value(DispProp::G, settings.gamma[1]);
+            d.set_value(DispProp::B, settings.gamma[2]);
+        });
+        let backlight = match settings.backlight {
+            Some(val) => self.set_value_by_index(index, DispProp::Backlight, val),
+            None => Ok(()),
+        };
+        let luminance = match settings.luminance {
+            Some(val) => self.set_value_by_index(index, DispProp::Luminance, val),
+            None => Ok(()),
+        };
+        color.and(backlight).and(luminance)
     }
 
-    pub fn increment_value_by_index(&mut self, index: usize, prop: DispProp) {
-        self.disps[index].increment_value(prop);
+    ///Put every display an entry changed back to where it was. Displays that are gone are skipped.
+    ///Every change is tried even if one fails, the first error is returned
+    pub fn undo(&mut self, entry: &Entry) -> Result<()> {
+        let mut result = Ok(());
+        for c in &entry.changes {
+            result = result.and(self.set_change(&c.output, c.prop, c.from));
+        }
+        result
     }
 
-    pub fn decrement_value_by_index(&mut self, index: usize, prop: DispProp) {
-        self.disps[index].decrement_value(prop);
+    ///Make the changes of an entry again
+    pub fn redo(&mut self, entry: &Entry) -> Result<()> {
+        let mut result = Ok(());
+        for c in &entry.changes {
+            result = result.and(self.set_change(&c.output, c.prop, c.to));
+        }
+        result
     }
 
-    pub fn scale_value_by_index(&mut self, index: usize, prop: DispProp, scale: u8) {
-        if scale > 0 && scale <= 10 {
-            self.disps[index].set_value(prop, scale*10);
-        } else {
-            self.disps[index].set_value(prop, 100);
+    ///Set a value the way history::Change keeps it. Both ways go in the order the entry has, so a
+    ///temperature is set before the R, G and B it would otherwise overwrite
+    fn set_change(&mut self, output: &str, prop: DispProp, value: u16) -> Result<()> {
+        let index = match self.disps.iter().position(|d| d.name == output) {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        match prop {
+            DispProp::Temperature => self.set_temperature_by_index(index, value),
+            DispProp::Vcp(code) => self.update(index, prop, |d| d.set_vcp(code, value)),
+            _ => self.set_value_by_index(index, prop, value as u8),
         }
     }
 
-    pub fn reload(&mut self) {
-        *self = DispMgr::new();
+    ///Settings of every display
+    pub fn snapshot(&self) -> Profile {
+        self.disps.iter().map(|d| (d.name.clone(), Settings::from(d))).collect()
+    }
+
+    ///Apply a profile, matching displays by name. Displays the profile doesn't know are left alone
+    pub fn apply_profile(&mut self, profile: &Profile) -> Result<()> {
+        let mut result = Ok(());
+        for i in 0..self.disps.len() {
+            if let Some(settings) = profile.get(&self.disps[i].name) {
+                result = result.and(self.apply_settings(i, settings));
+            }
+        }
+        result
+    }
+
+    pub fn scale_value_by_index(&mut self, index: usize, prop: DispProp, scale: u8) -> Result<()> {
+        self.set_value_by_index(index, prop, scale_to_value(scale))
+    }
+
+    ///Ask the backend for the connected outputs again and rebuild the list of displays. Displays
+    ///that are still connected keep their values, only new ones are read from the hardware.
+    ///Returns whether anything changed
+    pub fn reload(&mut self) -> Result<bool> {
+        let names = self.backend.outputs()?;
+        if !self.disps.is_empty() && names == self.get_name_list() {
+            return Ok(false);
+        }
+        self.backlight.scan(&names);
+        self.ddc.scan(&names);
+        let mut disps = Vec::new();
+        for name in names {
+            if let Some(disp) = self.disps.iter().find(|d| d.name == name) {
+                disps.push(disp.clone());
+                continue;
+            }
+            let (brightness, gamma) = self.backend.read_state(&name)?;
+            let mut disp = Disp::new(name, brightness, gamma);
+            disp.temperature = temperature::from_gamma(gamma);
+            //a backlight can go away under a hotplug, and monitors drop ddc requests now and then.
+            //that shouldn't stop everything else
+            disp.backlight = self.backlight.read(&disp.name).unwrap_or_else(|e| {
+                warn!("Could not read backlight of {}: {}", disp.name, e);
+                None
+            });
+            disp.luminance = self.ddc.read(&disp.name).unwrap_or_else(|e| {
+                warn!("Could not read luminance of {}: {}", disp.name, e);
+                None
+            });
+            disp.vcp = self.ddc.read_features(&disp.name).unwrap_or_else(|e| {
+                warn!("Could not read monitor settings of {}: {}", disp.name, e);
+                Vec::new()
+            });
+            disps.push(disp);
+        }
+        self.disps = disps;
+        Ok(true)
+    }
+
+    ///Change a copy of a display and apply it. The change is only kept if applying it worked, so
+    ///disps never shows something the hardware didn't accept
+    fn update(&mut self, index: usize, prop: DispProp, change: impl FnOnce(&mut Disp)) -> Result<()> {
+        let mut disp = self.get_disp_by_index(index)?;
+        change(&mut disp);
+        self.apply(&disp, prop)?;
+        self.disps[index] = disp;
+        Ok(())
+    }
+
+    ///Push a changed prop of a display out to whatever controls it
+    fn apply(&mut self, disp: &Disp, prop: DispProp) -> Result<()> {
+        match prop {
+            DispProp::Backlight => match disp.backlight {
+                Some(val) => self.backlight.write(&disp.name, val),
+                None => Ok(()),
+            },
+            DispProp::Luminance => match disp.luminance {
+                Some(val) => self.ddc.write(&disp.name, val),
+                None => Ok(()),
+            },
+            DispProp::Vcp(code) => match disp.vcp_feature(code) {
+                Some(feature) => self.ddc.write_feature(&disp.name, feature),
+                None => Ok(()),
+            },
+            _ => self.backend.apply(disp),
+        }
+    }
+}
+
+///Value a scale key (0-9) sets a DispProp to. 1-9 is 10%-90%, anything else is 100%
+pub fn scale_to_value(scale: u8) -> u8 {
+    if scale > 0 && scale <= 10 {
+        scale * 10
+    } else {
+        100
     }
 }
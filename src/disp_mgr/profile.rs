@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use super::disp::{Disp, Gamma};
+use super::error::{DispError, Result};
+use super::temperature;
+
+///Everything about a display that a profile or a sync carries over. Monitor settings are left out,
+///they mean different things on different monitors
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Settings {
+    pub brightness: u8,
+    ///Red, green and blue in percent
+    pub gamma: [u8; 3],
+    #[serde(default = "default_temperature")]
+    pub temperature: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backlight: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub luminance: Option<u8>,
+}
+
+fn default_temperature() -> u16 {
+    temperature::NEUTRAL
+}
+
+impl Settings {
+    pub fn gamma(&self) -> Gamma {
+        Gamma { r: self.gamma[0], g: self.gamma[1], b: self.gamma[2] }
+    }
+
+    ///Say what is out of range, if anything. The files settings come from can be edited by hand.
+    ///Hardware can be read at 0, so backlight and luminance may be, the gamma ramps can't
+    pub fn check(&self) -> std::result::Result<(), String> {
+        let percent = |name: &str, val: u8, min: u8| match val {
+            val if (min..=100).contains(&val) => Ok(()),
+            _ => Err(format!("{} {} is not a percentage from {} to 100", name, val, min)),
+        };
+        percent("brightness", self.brightness, 1)?;
+        for (name, val) in ["red", "green", "blue"].into_iter().zip(self.gamma) {
+            percent(name, val, 1)?;
+        }
+        if let Some(backlight) = self.backlight {
+            percent("backlight", backlight, 0)?;
+        }
+        if let Some(luminance) = self.luminance {
+            percent("luminance", luminance, 0)?;
+        }
+        if !(temperature::MIN..=temperature::MAX).contains(&self.temperature) {
+            return Err(format!(
+                "temperature {}K is not from {}K to {}K",
+                self.temperature,
+                temperature::MIN,
+                temperature::MAX
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl From<&Disp> for Settings {
+    fn from(disp: &Disp) -> Self {
+        Settings {
+            brightness: disp.brightness,
+            gamma: [disp.gamma.r, disp.gamma.g, disp.gamma.b],
+            temperature: disp.temperature,
+            backlight: disp.backlight,
+            luminance: disp.luminance,
+        }
+    }
+}
+
+///Settings of every display, by output name
+pub type Profile = BTreeMap<String, Settings>;
+
+///Named profiles, kept in profiles.toml next to the config. tuib writes this file itself, so it is
+///kept apart from config.toml
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profiles {
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl Profiles {
+    ///$XDG_CONFIG_HOME/tuib/profiles.toml
+    pub fn path() -> Option<PathBuf> {
+        Some(crate::config::Config::dir()?.join("profiles.toml"))
+    }
+
+    ///Load profiles from a file. A file that doesn't exist has no profiles, a profile with values
+    ///out of range is an error
+    pub fn load(path: &Path) -> Result<Profiles> {
+        let profiles: Profiles = read_toml(path)?;
+        for (name, profile) in &profiles.profiles {
+            check_profile(&format!("profile {} in {}", name, path.display()), profile)?;
+        }
+        Ok(profiles)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_toml(path, self)
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Profile> {
+        self.profiles
+            .get(name)
+            .ok_or_else(|| DispError::Profile(format!("no profile named {}", name)))
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.profiles.keys().cloned().collect()
+    }
+}
+
+///What every display was last set to, so it can be put back after X forgets it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct State {
+    #[serde(default)]
+    pub displays: Profile,
+}
+
+impl State {
+    ///$XDG_STATE_HOME/tuib/state.toml, or ~/.local/state/tuib/state.toml
+    pub fn path() -> Option<PathBuf> {
+        let dir = match std::env::var_os("XDG_STATE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".local").join("state"),
+        };
+        Some(dir.join("tuib").join("state.toml"))
+    }
+
+    ///Load the state from a file. A file that doesn't exist has no displays, values out of range are
+    ///an error
+    pub fn load(path: &Path) -> Result<State> {
+        let state: State = read_toml(path)?;
+        check_profile(&path.display().to_string(), &state.displays)?;
+        Ok(state)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_toml(path, self)
+    }
+}
+
+///Fail on the first display in profile with settings out of range. what says where it came from
+fn check_profile(what: &str, profile: &Profile) -> Result<()> {
+    for (output, settings) in profile {
+        settings
+            .check()
+            .map_err(|e| DispError::Profile(format!("{}: {}: {}", what, output, e)))?;
+    }
+    Ok(())
+}
+
+///Read a toml file tuib wrote. A file that doesn't exist gives the default
+fn read_toml<T: DeserializeOwned + Default>(path: &Path) -> Result<T> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => toml::from_str(&text)
+            .map_err(|e| DispError::Profile(format!("invalid {}: {}", path.display(), e))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(DispError::Profile(format!("could not read {}: {}", path.display(), e))),
+    }
+}
+
+///Write a toml file, making its directory if needed
+fn write_toml<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let text = toml::to_string(value).map_err(|e| DispError::Profile(e.to_string()))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, text)
+        .map_err(|e| DispError::Profile(format!("could not write {}: {}", path.display(), e)))
+}
@@ -0,0 +1,80 @@
+use super::disp::Gamma;
+
+///Coldest and warmest temperatures the Temperature gauge goes to, in Kelvin
+pub const MIN: u16 = 1000;
+pub const MAX: u16 = 10000;
+///Temperature of the sRGB white point. Gamma is neutral here
+pub const NEUTRAL: u16 = 6500;
+///How far one increment moves the gauge
+pub const STEP: u16 = 100;
+
+///sRGB color of a blackbody every 500K from 1000K to 10000K, from Mitchell Charity's blackbody
+///color datafile (CIE 1964 10 degree observer)
+const BLACKBODY: [(u16, [u8; 3]); 19] = [
+    (1000, [255, 56, 0]),
+    (1500, [255, 109, 0]),
+    (2000, [255, 137, 18]),
+    (2500, [255, 161, 72]),
+    (3000, [255, 180, 107]),
+    (3500, [255, 196, 137]),
+    (4000, [255, 209, 163]),
+    (4500, [255, 219, 186]),
+    (5000, [255, 228, 206]),
+    (5500, [255, 236, 224]),
+    (6000, [255, 243, 239]),
+    (6500, [255, 249, 253]),
+    (7000, [245, 243, 255]),
+    (7500, [235, 238, 255]),
+    (8000, [227, 233, 255]),
+    (8500, [220, 229, 255]),
+    (9000, [214, 225, 255]),
+    (9500, [208, 222, 255]),
+    (10000, [204, 219, 255]),
+];
+
+///RGB multipliers for a temperature, interpolated from the blackbody table. Scaled so 6500K is
+///1:1:1 and the strongest channel is always 1, so changing temperature never changes brightness
+pub fn multipliers(kelvin: u16) -> [f64; 3] {
+    let kelvin = kelvin.clamp(MIN, MAX);
+    let i = BLACKBODY
+        .iter()
+        .rposition(|(k, _)| *k <= kelvin)
+        .unwrap_or(0)
+        .min(BLACKBODY.len() - 2);
+    let (k0, c0) = BLACKBODY[i];
+    let (k1, c1) = BLACKBODY[i + 1];
+    let t = (kelvin - k0) as f64 / (k1 - k0) as f64;
+
+    let neutral = BLACKBODY.iter().find(|(k, _)| *k == NEUTRAL).unwrap().1;
+    let mut rgb = [0.0; 3];
+    for c in 0..3 {
+        let v = c0[c] as f64 + (c1[c] as f64 - c0[c] as f64) * t;
+        rgb[c] = v / neutral[c] as f64;
+    }
+    let max = rgb.iter().cloned().fold(f64::MIN, f64::max);
+    rgb.map(|v| v / max)
+}
+
+///Gamma for a temperature
+pub fn to_gamma(kelvin: u16) -> Gamma {
+    let [r, g, b] = multipliers(kelvin).map(|v| (v * 100.0).round().clamp(1.0, 100.0) as u8);
+    Gamma { r, g, b }
+}
+
+///Closest temperature to a gamma, to guess where the gauge starts
+pub fn from_gamma(gamma: Gamma) -> u16 {
+    let target = [gamma.r, gamma.g, gamma.b].map(|v| v as f64 / 100.0);
+    (MIN..=MAX)
+        .step_by(STEP as usize)
+        .min_by(|a, b| {
+            let dist = |k: u16| {
+                multipliers(k)
+                    .iter()
+                    .zip(target.iter())
+                    .map(|(m, t)| (m - t).powi(2))
+                    .sum::<f64>()
+            };
+            dist(*a).total_cmp(&dist(*b))
+        })
+        .unwrap_or(NEUTRAL)
+}
@@ -1,4 +1,5 @@
 use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
 
 use crossterm::event;
 
@@ -42,6 +43,44 @@ impl Display for Key {
     }
 }
 
+///Parse keys the way Display prints them: `<q>`, `<Ctrl+l>`, `<Alt+Space>`, `<Enter>`. The brackets
+///can be left out
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let inner = s
+            .strip_prefix('<')
+            .and_then(|s| s.strip_suffix('>'))
+            .unwrap_or(s);
+        let char_of = |name: &str| -> Option<char> {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(c),
+                _ if name.eq_ignore_ascii_case("space") => Some(' '),
+                _ => None,
+            }
+        };
+        let key = match inner.to_ascii_lowercase().as_str() {
+            "enter" => Some(Key::Enter),
+            "tab" => Some(Key::Tab),
+            "backspace" => Some(Key::Backspace),
+            "esc" => Some(Key::Esc),
+            "left" => Some(Key::Left),
+            "right" => Some(Key::Right),
+            "up" => Some(Key::Up),
+            "down" => Some(Key::Down),
+            "delete" => Some(Key::Delete),
+            lower => match lower.split_once('+') {
+                Some(("ctrl", _)) => char_of(&inner[5..]).map(Key::Ctrl),
+                Some(("alt", _)) => char_of(&inner[4..]).map(Key::Alt),
+                _ => char_of(inner).map(Key::Char),
+            },
+        };
+        key.ok_or_else(|| format!("unknown key '{}'", s))
+    }
+}
+
 impl From<event::KeyEvent> for Key {
     fn from(key_event: event::KeyEvent) -> Self {
         match key_event {
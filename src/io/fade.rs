@@ -0,0 +1,51 @@
+use std::time::{Duration, Instant};
+use crate::disp_mgr::disp::DispProp;
+
+///How often a fade moves on
+pub const FRAME: Duration = Duration::from_millis(33);
+
+///A DispProp of one display moving from one value to another over time. Keeps the display by name
+///so it survives displays coming and going
+#[derive(Debug, Clone)]
+pub struct Fade {
+    pub name: String,
+    pub prop: DispProp,
+    from: u8,
+    to: u8,
+    start: Instant,
+    duration: Duration,
+    ///Last value applied, to skip frames that wouldn't change anything
+    last: u8,
+}
+
+impl Fade {
+    pub fn new(name: String, prop: DispProp, from: u8, to: u8, start: Instant, duration: Duration) -> Self {
+        Fade { name, prop, from, to, start, duration, last: from }
+    }
+
+    ///Value the fade is at, at time now
+    pub fn value_at(&self, now: Instant) -> u8 {
+        let t = now.saturating_duration_since(self.start).as_secs_f64() / self.duration.as_secs_f64();
+        let t = if t.is_finite() { t.clamp(0.0, 1.0) } else { 1.0 };
+        (self.from as f64 + (self.to as f64 - self.from as f64) * t).round() as u8
+    }
+
+    ///Move on to now. Returns the value to apply, or None if it hasn't changed since the last frame
+    pub fn step(&mut self, now: Instant) -> Option<u8> {
+        let value = self.value_at(now);
+        if value == self.last {
+            return None;
+        }
+        self.last = value;
+        Some(value)
+    }
+
+    ///Value the fade ends at
+    pub fn to(&self) -> u8 {
+        self.to
+    }
+
+    pub fn is_done(&self, now: Instant) -> bool {
+        now >= self.start + self.duration
+    }
+}
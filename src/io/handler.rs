@@ -1,72 +1,627 @@
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use parking_lot::FairMutex;
-use eyre::Result;
 use log::{error, info};
+use super::fade::{Fade, FRAME};
 use super::IoEvent;
 use crate::app::App;
-use crate::disp_mgr::{disp::DispProp, DispMgr};
+use crate::cli::Report;
+use crate::disp_mgr::error::{DispError, Result};
+use crate::disp_mgr::{backend::DisplayBackend, disp::DispProp, scale_to_value, DispMgr};
+use crate::disp_mgr::group::{Group, Member};
+use crate::disp_mgr::history::{self, Entry, History};
+use crate::disp_mgr::profile::{Profile, Profiles, State};
+use crate::ipc::{Mode, Request, Response};
+use crate::night_light::{self, Target};
 
-pub struct IoHandler {
+///How often the state of the displays is saved, if it changed
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+///Everything a sync or a profile sets
+const SETTINGS_PROPS: [DispProp; 7] = [
+    DispProp::Brightness,
+    DispProp::Backlight,
+    DispProp::Luminance,
+    DispProp::Temperature,
+    DispProp::R,
+    DispProp::G,
+    DispProp::B,
+];
+
+pub struct IoHandler<B: DisplayBackend> {
     app: Arc<FairMutex<App>>,
-    disp_mgr: DispMgr,
+    disp_mgr: DispMgr<B>,
+    ///How long scale keys take to get to their value
+    fade: Duration,
+    fades: Vec<Fade>,
+    ///Whether changes to one display go to all of them
+    linked: bool,
+    groups: Vec<Group>,
+    ///Group whose tab is open
+    group: Option<usize>,
+    profiles: Profiles,
+    ///Where profiles are saved. Not saved anywhere if None
+    profiles_path: Option<PathBuf>,
+    ///Where the state of the displays is saved. Not saved anywhere if None
+    state_path: Option<PathBuf>,
+    ///What was last saved there, so unchanged state isn't written again
+    saved_state: Option<State>,
+    ///Changes made from the UI, to undo and redo
+    history: History,
+    ///Clients of the daemon that want to know when displays change
+    subscribers: Vec<Sender<Response>>,
+    ///What subscribers were last sent
+    notified: Option<Vec<Report>>,
+    ///Set by IoEvent::Shutdown
+    shut_down: bool,
 }
 
-impl IoHandler {
-    pub fn new(app: Arc<FairMutex<App>>) -> Self {
-        let disp_mgr = DispMgr::new();
+impl<B: DisplayBackend> IoHandler<B> {
+    pub fn new(app: Arc<FairMutex<App>>, disp_mgr: DispMgr<B>) -> Self {
         Self {
             app,
             disp_mgr,
+            fade: Duration::ZERO,
+            fades: Vec::new(),
+            linked: false,
+            groups: Vec::new(),
+            group: None,
+            profiles: Profiles::default(),
+            profiles_path: None,
+            state_path: None,
+            saved_state: None,
+            history: History::default(),
+            subscribers: Vec::new(),
+            notified: None,
+            shut_down: false,
+        }
+    }
+
+    ///Set how long scale keys take to get to their value. Zero jumps straight there
+    pub fn set_fade(&mut self, fade: Duration) {
+        self.fade = fade;
+    }
+
+    ///Set the groups, in the same order the App has them
+    pub fn set_groups(&mut self, groups: Vec<Group>) {
+        self.groups = groups;
+    }
+
+    ///Set the profiles, and where to save them when they change
+    pub fn set_profiles(&mut self, profiles: Profiles, path: Option<PathBuf>) {
+        self.profiles = profiles;
+        self.profiles_path = path;
+    }
+
+    ///Set where the state of the displays is saved on shutdown and every SAVE_INTERVAL
+    pub fn set_state_path(&mut self, path: Option<PathBuf>) {
+        self.state_path = path;
+    }
+
+    ///Get the profiles
+    pub fn profiles(&self) -> &Profiles {
+        &self.profiles
+    }
+
+    ///Whether any fades are still running
+    pub fn is_fading(&self) -> bool {
+        !self.fades.is_empty()
+    }
+
+    ///Handle IoEvents until IoEvent::Shutdown or every sender is gone. Wakes up every frame while
+    ///something is fading, and every SAVE_INTERVAL to save the state
+    pub fn run(mut self, io_rx: Receiver<IoEvent>) {
+        let mut last_save = Instant::now();
+        loop {
+            let timeout = if self.is_fading() { FRAME } else { SAVE_INTERVAL };
+            match io_rx.recv_timeout(timeout) {
+                Ok(io_event) => self.handle_io_event(io_event),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            if self.shut_down {
+                break;
+            }
+            self.tick(Instant::now());
+            if last_save.elapsed() >= SAVE_INTERVAL {
+                last_save = Instant::now();
+                if let Err(e) = self.save_state() {
+                    error!("Error saving state: {}", e);
+                }
+            }
+        }
+    }
+
+    ///Save the state of every display, unless it's the same as last time
+    pub fn save_state(&mut self) -> Result<()> {
+        let path = match &self.state_path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let state = State { displays: self.disp_mgr.snapshot() };
+        if self.saved_state.as_ref() != Some(&state) {
+            state.save(path)?;
+            self.saved_state = Some(state);
+        }
+        Ok(())
+    }
+
+    ///Move every fade on to now, and drop the ones that are done
+    pub fn tick(&mut self, now: Instant) {
+        if self.fades.is_empty() {
+            return;
+        }
+        let mut error = None;
+        let mut fades = std::mem::take(&mut self.fades);
+        fades.retain_mut(|fade| {
+            let index = match self.disp_mgr.disps.iter().position(|d| d.name == fade.name) {
+                Some(index) => index,
+                None => return false,
+            };
+            if let Some(value) = fade.step(now) {
+                if let Err(e) = self.disp_mgr.set_value_by_index(index, fade.prop, value) {
+                    error.get_or_insert(e);
+                    return false;
+                }
+            }
+            !fade.is_done(now)
+        });
+        self.fades = fades;
+        self.notify();
+
+        let mut app = self.app.lock();
+        app.state.set_disps(self.disp_mgr.disps.clone());
+        if let Some(err) = error {
+            error!("Error in io::handler::tick: {}", err);
+            app.set_status(Some(err.to_string()));
         }
     }
 
+    ///Stop fades of a display, or of all displays if None, that change any of props. Input on a
+    ///prop that is fading takes over from the fade
+    fn cancel_fades(&mut self, device_index: Option<usize>, props: &[DispProp]) {
+        let name = device_index.and_then(|i| self.disp_mgr.disps.get(i)).map(|d| d.name.clone());
+        self.fades.retain(|fade| {
+            let same_disp = name.as_ref().is_none_or(|name| *name == fade.name);
+            !(same_disp && props.contains(&fade.prop))
+        });
+    }
+
+    ///Get the undo and redo history
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    ///Get the DispMgr. Used to check state from outside the IO thread, eg in tests
+    pub fn disp_mgr(&self) -> &DispMgr<B> {
+        &self.disp_mgr
+    }
+
     ///Call different function depending on IoEvent
     pub fn handle_io_event(&mut self, io_event: IoEvent) {
+        //reloads, the night light and clients of the daemon happen in the background all the time,
+        //they shouldn't hide the last error. Neither should starting up, eg after a bad profiles.toml
+        let clears_status =
+            !matches!(io_event, IoEvent::Initialize | IoEvent::Reload | IoEvent::NightLight(_) | IoEvent::Ipc(..));
+        let result = self.handle(io_event);
+        self.notify();
+
+        let mut app = self.app.lock();
+        app.set_history(self.history.clone());
+        match result {
+            Ok(()) if clears_status => app.set_status(None),
+            Ok(()) => {}
+            Err(err) => {
+                error!("Error in io::handler::handle_io_event: {}", err);
+                app.set_status(Some(err.to_string()));
+            }
+        }
+        app.loaded();
+    }
+
+    ///Do what an IoEvent asks. Changes go in the history, as one entry per event
+    fn handle(&mut self, io_event: IoEvent) -> Result<()> {
+        let recorded = matches!(
+            io_event,
+            IoEvent::DeviceIncrement(..)
+                | IoEvent::DeviceDecrement(..)
+                | IoEvent::DeviceScale(..)
+                | IoEvent::DeviceStep(..)
+                | IoEvent::DeviceFade(..)
+                | IoEvent::SetTemperature(..)
+                | IoEvent::Sync(_)
+                | IoEvent::ApplyProfile(_)
+                | IoEvent::Restore
+        );
+        let before = if recorded { self.values() } else { vec![] };
         let result = match io_event {
             IoEvent::Initialize => self.do_initialize(),
+            IoEvent::Reload => self.do_reload(),
             IoEvent::DeviceIncrement(device_index, prop) => self.do_increment(device_index, prop),
             IoEvent::DeviceDecrement(device_index, prop) => self.do_decrement(device_index, prop),
-            IoEvent::DeviceScale(device_index, prop, scale) => self.do_scale(device_index, prop, scale),
+            IoEvent::DeviceScale(device_index, prop, scale) => self.do_fade(device_index, prop, scale_to_value(scale), self.fade),
+            IoEvent::DeviceStep(device_index, prop, delta) => self.do_step(device_index, prop, delta),
+            IoEvent::DeviceFade(device_index, prop, value, duration) => self.do_fade(device_index, prop, value, duration),
+            IoEvent::SetTemperature(device_index, kelvin) => self.do_set_temperature(device_index, kelvin),
+            IoEvent::NightLight(target) => self.do_night_light(target),
+            IoEvent::Sync(device_index) => self.do_sync(device_index),
+            IoEvent::Link(linked) => self.do_link(linked),
+            IoEvent::SaveProfile(name) => self.do_save_profile(name),
+            IoEvent::Shutdown => {
+                self.shut_down = true;
+                self.save_state()
+            }
+            IoEvent::Ipc(request, mode, reply) => {
+                self.do_request(request, mode, reply);
+                Ok(())
+            }
+            IoEvent::Undo => self.do_undo(),
+            IoEvent::Redo => self.do_redo(),
+            IoEvent::ApplyProfile(name) => self.do_apply_profile(&name),
+            IoEvent::Restore => self.do_restore(),
+            IoEvent::Group(group) => {
+                self.group = group;
+                Ok(())
+            }
         };
 
-        if let Err(err) = result {
-            error!("Error in io::handler::handle_io_event: {:?}", err);
+        if recorded {
+            self.history.push(history::diff(&before, &self.values()));
         }
+        result
+    }
 
-        let mut app = self.app.lock();
-        app.loaded();
+    ///Answer a request from a client of the daemon, sent with the mode of its connection
+    fn do_request(&mut self, request: Request, mode: Mode, reply: Sender<Response>) {
+        let response = match request {
+            //the connection keeps these, here they only have to make sense
+            Request::Link { .. } => Response::Ok,
+            Request::Group { name } => match name.map(|name| self.group_index(&name)).transpose() {
+                Ok(_) => Response::Ok,
+                Err(e) => Response::Error { message: e.to_string() },
+            },
+            Request::List => Response::Displays { displays: self.reports() },
+            Request::Profiles => Response::Profiles { profiles: self.profiles.names() },
+            Request::History => Response::History { history: self.history.clone() },
+            Request::Subscribe => {
+                //bring the others up to date first, so nobody is sent the same displays twice
+                self.notify();
+                let displays = self.reports();
+                let _ = reply.send(Response::Ok);
+                let _ = reply.send(Response::Changed { displays: displays.clone() });
+                self.notified = Some(displays);
+                self.subscribers.push(reply);
+                return;
+            }
+            request => {
+                //changes spread the way this client set up, not the way the last one to ask left it
+                let group = mode.group.and_then(|name| self.group_index(&name).ok());
+                let own = (self.linked, self.group);
+                (self.linked, self.group) = (mode.linked, group);
+                let result = self.to_io_event(request).and_then(|io_event| self.handle(io_event));
+                (self.linked, self.group) = own;
+                match result {
+                    Ok(()) => Response::Ok,
+                    Err(e) => Response::Error { message: e.to_string() },
+                }
+            }
+        };
+        let _ = reply.send(response);
+    }
+
+    ///The IoEvent a request mirrors
+    fn to_io_event(&self, request: Request) -> Result<IoEvent> {
+        let index = |output: &str| self.disp_mgr.index_of(output);
+        Ok(match request {
+            Request::Reload => IoEvent::Reload,
+            Request::Increment { output, prop } => IoEvent::DeviceIncrement(index(&output)?, prop),
+            Request::Decrement { output, prop } => IoEvent::DeviceDecrement(index(&output)?, prop),
+            Request::Step { output, prop, delta } => IoEvent::DeviceStep(index(&output)?, prop, delta),
+            Request::Scale { output, prop, scale } => IoEvent::DeviceScale(index(&output)?, prop, scale),
+            Request::Set { output, prop, value, fade_ms } => {
+                IoEvent::DeviceFade(index(&output)?, prop, value, Duration::from_millis(fade_ms))
+            }
+            Request::Temperature { output, kelvin } => {
+                IoEvent::SetTemperature(output.map(|o| index(&o)).transpose()?, kelvin)
+            }
+            Request::NightLight { kelvin, brightness } => IoEvent::NightLight(Target { kelvin, brightness }),
+            Request::Sync { output } => IoEvent::Sync(index(&output)?),
+            Request::SaveProfile { name } => IoEvent::SaveProfile(name),
+            Request::ApplyProfile { name } => IoEvent::ApplyProfile(name),
+            Request::Restore => IoEvent::Restore,
+            Request::Undo => IoEvent::Undo,
+            Request::Redo => IoEvent::Redo,
+            Request::Shutdown => IoEvent::Shutdown,
+            Request::List | Request::Profiles | Request::History | Request::Subscribe | Request::Link { .. } | Request::Group { .. } => {
+                return Err(DispError::Daemon("not an event".to_string()))
+            }
+        })
+    }
+
+    ///Index of the group with a name
+    fn group_index(&self, name: &str) -> Result<usize> {
+        self.groups
+            .iter()
+            .position(|g| g.name == name)
+            .ok_or_else(|| DispError::Unsupported(format!("no group named {}", name)))
+    }
+
+    ///Every display, the way clients of the daemon see them
+    fn reports(&self) -> Vec<Report> {
+        let backend = self.disp_mgr.backend().name();
+        self.disp_mgr.disps.iter().map(|d| Report::new(d, backend)).collect()
+    }
+
+    ///Send the displays to subscribers if they changed since they were last sent. Subscribers that
+    ///went away are dropped
+    fn notify(&mut self) {
+        if self.subscribers.is_empty() {
+            return;
+        }
+        let reports = self.reports();
+        if self.notified.as_ref() == Some(&reports) {
+            return;
+        }
+        self.subscribers.retain(|s| s.send(Response::Changed { displays: reports.clone() }).is_ok());
+        self.notified = Some(reports);
     }
 
     ///Initialize the application
     fn do_initialize(&mut self) -> Result<()> {
         info!("Initialized");
         let mut app = self.app.lock();
-        app.initialize(self.disp_mgr.clone());
+        app.initialize(self.disp_mgr.disps.clone());
+        app.set_profiles(self.profiles.names());
         info!("Application initialized");
         Ok(())
     }
 
+    ///Look for connected or disconnected outputs and update the UI if anything changed
+    fn do_reload(&mut self) -> Result<()> {
+        if self.disp_mgr.reload()? {
+            info!("Displays changed: {}", self.disp_mgr.get_name_list().join(", "));
+            self.app.lock().state.set_disps(self.disp_mgr.disps.clone());
+        }
+        Ok(())
+    }
+
+    ///Save the settings of every display under name, replacing any profile with that name
+    fn do_save_profile(&mut self, name: String) -> Result<()> {
+        info!("Saving profile {}", name);
+        self.profiles.profiles.insert(name, self.disp_mgr.snapshot());
+        self.app.lock().set_profiles(self.profiles.names());
+        match &self.profiles_path {
+            Some(path) => self.profiles.save(path),
+            None => Ok(()),
+        }
+    }
+
+    ///Apply a profile to the displays it knows
+    fn do_apply_profile(&mut self, name: &str) -> Result<()> {
+        let profile = self.profiles.get(name)?.clone();
+        info!("Applying profile {}", name);
+        self.apply_profile(&profile)
+    }
+
+    ///Put the displays back the way the saved state has them. Nothing to do without a state path
+    fn do_restore(&mut self) -> Result<()> {
+        let state = match &self.state_path {
+            Some(path) => State::load(path)?,
+            None => return Ok(()),
+        };
+        info!("Restoring the saved state");
+        self.apply_profile(&state.displays)
+    }
+
+    ///Apply a profile or state, stopping fades it would fight with
+    fn apply_profile(&mut self, profile: &Profile) -> Result<()> {
+        self.cancel_fades(None, &SETTINGS_PROPS);
+        let result = self.disp_mgr.apply_profile(profile);
+        self.app.lock().state.set_disps(self.disp_mgr.disps.clone());
+        result
+    }
+
+    ///Every value of every display as the history keeps them. Fading props are taken to be where
+    ///they are fading to, that's where the input that started the fade put them
+    fn values(&self) -> Vec<(String, DispProp, u16)> {
+        let disps: Vec<_> = self
+            .disp_mgr
+            .disps
+            .iter()
+            .cloned()
+            .map(|mut disp| {
+                for fade in &self.fades {
+                    if fade.name == disp.name {
+                        disp.set_value(fade.prop, fade.to());
+                    }
+                }
+                disp
+            })
+            .collect();
+        history::values_of(&disps)
+    }
+
+    ///Put back the last change in the history
+    fn do_undo(&mut self) -> Result<()> {
+        let entry = match self.history.undo() {
+            Some(entry) => entry,
+            None => return Ok(()),
+        };
+        info!("Undo {}", entry);
+        self.cancel_entry_fades(&entry);
+        let result = self.disp_mgr.undo(&entry);
+        self.app.lock().state.set_disps(self.disp_mgr.disps.clone());
+        result
+    }
+
+    ///Make the last undone change in the history again
+    fn do_redo(&mut self) -> Result<()> {
+        let entry = match self.history.redo() {
+            Some(entry) => entry,
+            None => return Ok(()),
+        };
+        info!("Redo {}", entry);
+        self.cancel_entry_fades(&entry);
+        let result = self.disp_mgr.redo(&entry);
+        self.app.lock().state.set_disps(self.disp_mgr.disps.clone());
+        result
+    }
+
+    ///Stop fades on anything entry changes, so they don't carry on after an undo or redo
+    fn cancel_entry_fades(&mut self, entry: &Entry) {
+        self.fades
+            .retain(|fade| !entry.changes.iter().any(|c| c.output == fade.name && c.prop == fade.prop));
+    }
+
+    ///Turn linked mode on or off
+    fn do_link(&mut self, linked: bool) -> Result<()> {
+        info!("Linked mode {}", if linked { "on" } else { "off" });
+        self.linked = linked;
+        Ok(())
+    }
+
+    ///Copy the settings of one display to every other one
+    fn do_sync(&mut self, device_index: usize) -> Result<()> {
+        if device_index == self.disp_mgr.get_num_disps() { return Ok(()); }
+        self.cancel_fades(None, &SETTINGS_PROPS);
+        let result = self.disp_mgr.sync_from_index(device_index);
+        self.app.lock().state.set_disps(self.disp_mgr.disps.clone());
+        result
+    }
+
+    ///The displays an input on device_index changes. Just that one, or all of them when linked.
+    ///Monitor settings differ between monitors so they are never linked
+    fn targets(&self, device_index: usize, prop: DispProp) -> Vec<usize> {
+        let num_disps = self.disp_mgr.get_num_disps();
+        if device_index >= num_disps {
+            vec![]
+        } else if self.linked && !matches!(prop, DispProp::Vcp(_)) {
+            (0..num_disps).collect()
+        } else {
+            vec![device_index]
+        }
+    }
+
+    ///Connected members of the open group, with their display index, if device_index is its
+    ///leader. Nothing follows in linked mode, everything gets the same change there
+    fn followers(&self, device_index: usize, prop: DispProp) -> Vec<(usize, Member)> {
+        let group = match self.group.and_then(|g| self.groups.get(g)) {
+            Some(group) if !self.linked && !matches!(prop, DispProp::Vcp(_)) => group,
+            _ => return vec![],
+        };
+        let disps = &self.disp_mgr.disps;
+        if disps.get(device_index).map(|d| &d.name) != Some(&group.leader) {
+            return vec![];
+        }
+        group
+            .members
+            .iter()
+            .filter_map(|m| Some((disps.iter().position(|d| d.name == m.output)?, m.clone())))
+            .collect()
+    }
+
+    ///Move the followers of device_index to where its prop is now
+    fn follow(&mut self, device_index: usize, prop: DispProp) -> Result<()> {
+        let leader = self.disp_mgr.get_disp_by_index(device_index)?.get_value(prop);
+        let mut result = Ok(());
+        for (i, member) in self.followers(device_index, prop) {
+            self.cancel_fades(Some(i), &[prop]);
+            result = result.and(self.disp_mgr.set_value_by_index(i, prop, member.value(leader)));
+        }
+        result
+    }
+
+    ///Run change on every target of device_index, then reflect changes in the UI. Every display is
+    ///tried even if one fails, the first error is returned
+    fn for_targets(
+        &mut self,
+        device_index: usize,
+        prop: DispProp,
+        mut change: impl FnMut(&mut Self, usize) -> Result<()>,
+    ) -> Result<()> {
+        let mut result = Ok(());
+        for i in self.targets(device_index, prop) {
+            self.cancel_fades(Some(i), &[prop]);
+            if let Err(e) = change(self, i) {
+                result = result.and(Err(e));
+            }
+        }
+        self.app.lock().state.set_disps(self.disp_mgr.disps.clone());
+        result
+    }
+
     ///Increment a single DispProp for a single device, and reflect changes in the UI
     fn do_increment(&mut self, device_index: usize, prop: DispProp) -> Result<()> {
-        if device_index == self.disp_mgr.get_num_disps() { return Ok(()); }
-        self.disp_mgr.increment_value_by_index(device_index, prop);
-        self.app.lock().state.set_disp_mgr(self.disp_mgr.clone());
-        Ok(())
+        self.for_targets(device_index, prop, |h, i| {
+            h.disp_mgr.increment_value_by_index(i, prop)?;
+            h.follow(i, prop)
+        })
+    }
+
+    ///Move a single DispProp for a single device by delta, and reflect changes in the UI
+    fn do_step(&mut self, device_index: usize, prop: DispProp, delta: i32) -> Result<()> {
+        self.for_targets(device_index, prop, |h, i| {
+            h.disp_mgr.step_value_by_index(i, prop, delta)?;
+            h.follow(i, prop)
+        })
     }
 
     ///Decrement a single DispProp for a single device, and reflect changes in the UI
     fn do_decrement(&mut self, device_index: usize, prop: DispProp) -> Result<()> {
-        if device_index == self.disp_mgr.get_num_disps() { return Ok(()); }
-        self.disp_mgr.decrement_value_by_index(device_index, prop);
-        self.app.lock().state.set_disp_mgr(self.disp_mgr.clone());
-        Ok(())
+        self.for_targets(device_index, prop, |h, i| {
+            h.disp_mgr.decrement_value_by_index(i, prop)?;
+            h.follow(i, prop)
+        })
     }
 
-    ///Scale a single DispProp for a single device and reflect changes in the UI
-    fn do_scale(&mut self, device_index: usize, prop: DispProp, scale: u8) -> Result<()> {
-        if device_index == self.disp_mgr.get_num_disps() { return Ok(()); }
-        self.disp_mgr.scale_value_by_index(device_index, prop, scale);
-        self.app.lock().state.set_disp_mgr(self.disp_mgr.clone());
-        Ok(())
+    ///Move a single DispProp for a single device to value over duration. Monitor settings jump
+    ///straight there, DDC/CI is too slow to animate and some of them are pick-lists
+    fn do_fade(&mut self, device_index: usize, prop: DispProp, value: u8, duration: Duration) -> Result<()> {
+        self.for_targets(device_index, prop, |h, i| {
+            if duration.is_zero() || matches!(prop, DispProp::Vcp(_)) {
+                h.disp_mgr.set_value_by_index(i, prop, value)?;
+                return h.follow(i, prop);
+            }
+            let now = Instant::now();
+            let mut fades = vec![(i, value)];
+            fades.extend(h.followers(i, prop).into_iter().map(|(mi, m)| (mi, m.value(value))));
+            for (i, value) in fades {
+                h.cancel_fades(Some(i), &[prop]);
+                let disp = h.disp_mgr.get_disp_by_index(i)?;
+                let from = disp.get_value(prop);
+                h.fades.push(Fade::new(disp.name, prop, from, value, now, duration));
+            }
+            Ok(())
+        })
+    }
+
+    ///Set every display to what the night light wants
+    fn do_night_light(&mut self, target: Target) -> Result<()> {
+        self.cancel_fades(None, &[DispProp::Brightness, DispProp::Temperature, DispProp::R, DispProp::G, DispProp::B]);
+        let result = night_light::apply(&mut self.disp_mgr, target);
+        self.app.lock().state.set_disps(self.disp_mgr.disps.clone());
+        result
+    }
+
+    ///Set the color temperature of one device or all of them. Every display is tried even if one
+    ///fails, the first error is returned
+    fn do_set_temperature(&mut self, device_index: Option<usize>, kelvin: u16) -> Result<()> {
+        self.cancel_fades(device_index, &[DispProp::Temperature, DispProp::R, DispProp::G, DispProp::B]);
+        let indexes = match device_index {
+            Some(i) if i == self.disp_mgr.get_num_disps() => return Ok(()),
+            Some(i) => vec![i],
+            None => (0..self.disp_mgr.get_num_disps()).collect(),
+        };
+        let mut result = Ok(());
+        for i in indexes {
+            if let Err(e) = self.disp_mgr.set_temperature_by_index(i, kelvin) {
+                error!("Error setting temperature of display {}: {}", i, e);
+                result = result.and(Err(e));
+            }
+        }
+        self.app.lock().state.set_disps(self.disp_mgr.disps.clone());
+        result
     }
 }
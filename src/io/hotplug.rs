@@ -0,0 +1,32 @@
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+use log::info;
+use super::IoEvent;
+use crate::disp_mgr::backend::DisplayBackend;
+
+///How often to check for new outputs when the backend can't tell us
+pub const POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+///Send IoEvent::Reload to the IO thread whenever outputs change. Uses the backend's own
+///notifications if it has them, otherwise polls
+pub fn start<B: DisplayBackend>(backend: &B, io_tx: Sender<IoEvent>) {
+    let notify_tx = io_tx.clone();
+    let watching = backend.watch(Box::new(move || {
+        let _ = notify_tx.send(IoEvent::Reload);
+    }));
+    if !watching {
+        info!("Backend can't report hotplug events, polling every {:?}", POLL_INTERVAL);
+        poll(io_tx, POLL_INTERVAL);
+    }
+}
+
+///Send IoEvent::Reload every interval until the IO thread goes away
+pub fn poll(io_tx: Sender<IoEvent>, interval: Duration) {
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        if io_tx.send(IoEvent::Reload).is_err() {
+            break;
+        }
+    });
+}
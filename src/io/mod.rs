@@ -1,10 +1,47 @@
+use std::sync::mpsc::Sender;
+use std::time::Duration;
 use crate::disp_mgr::disp::DispProp;
+use crate::ipc::{Mode, Request, Response};
+use crate::night_light::Target;
 
+pub mod fade;
 pub mod handler;
+pub mod hotplug;
 #[derive(Debug, Clone)]
 pub enum IoEvent {
     Initialize,
+    ///Outputs may have been connected or disconnected
+    Reload,
     DeviceIncrement(usize, DispProp),
     DeviceDecrement(usize, DispProp),
     DeviceScale(usize, DispProp, u8),
+    ///Move a DispProp by delta, in Kelvin for Temperature and percent for everything else
+    DeviceStep(usize, DispProp, i32),
+    ///Fade a DispProp of a display to a value over a duration. A zero duration sets it right away
+    DeviceFade(usize, DispProp, u8, Duration),
+    ///Set the color temperature of one display, or all of them if None
+    SetTemperature(Option<usize>, u16),
+    ///Night light moved on, set every display
+    NightLight(Target),
+    ///Copy the settings of a display to every other display
+    Sync(usize),
+    ///Send increments, decrements and scales to every display, or stop doing that
+    Link(bool),
+    ///The group whose tab is open, if any. Changes to its leader move its members
+    Group(Option<usize>),
+    ///Save the settings of every display as a named profile
+    SaveProfile(String),
+    ///Apply a named profile
+    ApplyProfile(String),
+    ///Put the displays back the way the saved state has them
+    Restore,
+    ///Put back the last change made from the UI
+    Undo,
+    ///Make the last undone change again
+    Redo,
+    ///Save the state of every display and stop handling events. Sent when the UI exits
+    Shutdown,
+    ///A request from a client of the daemon, the mode of its connection, and where to send the
+    ///response
+    Ipc(Request, Mode, Sender<Response>),
 }
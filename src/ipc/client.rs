@@ -0,0 +1,198 @@
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::thread;
+use log::{error, info};
+use parking_lot::FairMutex;
+use super::{Request, Response};
+use crate::app::App;
+use crate::cli::{Displays, Report};
+use crate::disp_mgr::disp::{Disp, DispProp};
+use crate::disp_mgr::error::{DispError, Result};
+use crate::disp_mgr::history::History;
+use crate::io::IoEvent;
+
+///A connection to a running `tuib daemon`
+pub struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Client {
+    ///Connect to the daemon listening at path. Fails if there is none
+    pub fn connect(path: &Path) -> Result<Self> {
+        let writer = UnixStream::connect(path)?;
+        let reader = BufReader::new(writer.try_clone()?);
+        Ok(Client { reader, writer })
+    }
+
+    ///Send a request and wait for its response
+    pub fn request(&mut self, request: &Request) -> Result<Response> {
+        let line = serde_json::to_string(request).map_err(|e| DispError::Parse(e.to_string()))?;
+        writeln!(self.writer, "{}", line)?;
+        self.read()
+    }
+
+    ///Read the next message from the daemon
+    fn read(&mut self) -> Result<Response> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(DispError::Daemon(String::from("connection closed")));
+        }
+        serde_json::from_str(&line).map_err(|e| DispError::Parse(e.to_string()))
+    }
+
+    ///Send a request that is answered with ok or an error
+    pub fn call(&mut self, request: &Request) -> Result<()> {
+        match self.request(request)? {
+            Response::Ok => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    ///Names of the saved profiles
+    pub fn profiles(&mut self) -> Result<Vec<String>> {
+        match self.request(&Request::Profiles)? {
+            Response::Profiles { profiles } => Ok(profiles),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    ///What the daemon can undo and redo
+    pub fn history(&mut self) -> Result<History> {
+        match self.request(&Request::History)? {
+            Response::History { history } => Ok(history),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    ///Ask to be told about changes. The iterator yields the displays right away, then every time
+    ///they change, and ends when the daemon goes away
+    pub fn subscribe(mut self) -> Result<impl Iterator<Item = Vec<Report>>> {
+        self.call(&Request::Subscribe)?;
+        Ok(std::iter::from_fn(move || match self.read() {
+            Ok(Response::Changed { displays }) => Some(displays),
+            _ => None,
+        }))
+    }
+}
+
+///The error for a response that isn't the one a request wants
+fn unexpected(response: Response) -> DispError {
+    match response {
+        Response::Error { message } => DispError::Daemon(message),
+        response => DispError::Daemon(format!("unexpected response {:?}", response)),
+    }
+}
+
+impl Displays for Client {
+    fn reports(&mut self) -> Result<Vec<Report>> {
+        match self.request(&Request::List)? {
+            Response::Displays { displays } => Ok(displays),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    fn set_value(&mut self, output: &str, prop: DispProp, value: u8) -> Result<()> {
+        self.call(&Request::Set { output: output.to_string(), prop, value, fade_ms: 0 })
+    }
+
+    fn step_value(&mut self, output: &str, prop: DispProp, delta: i32) -> Result<()> {
+        self.call(&Request::Step { output: output.to_string(), prop, delta })
+    }
+
+    fn set_temperature(&mut self, output: &str, kelvin: u16) -> Result<()> {
+        self.call(&Request::Temperature { output: Some(output.to_string()), kelvin })
+    }
+}
+
+///Stand in for the IO thread when the TUI is a client of a daemon. IoEvents from the UI are sent
+///to the daemon as Requests, and the displays and history it sends back are shown. Returns on
+///IoEvent::Shutdown, which is not passed on since the daemon outlives the UI
+pub fn bridge(app: Arc<FairMutex<App>>, path: &Path, io_rx: Receiver<IoEvent>) -> Result<()> {
+    let mut client = Client::connect(path)?;
+    let changes = Client::connect(path)?.subscribe()?;
+    //the history changes with the displays, from this client or any other
+    let mut history = Client::connect(path)?;
+    let app_changes = Arc::clone(&app);
+    thread::spawn(move || {
+        for displays in changes {
+            let history = history.history();
+            let mut app = app_changes.lock();
+            app.state.set_disps(displays.iter().map(Disp::from).collect());
+            if let Ok(history) = history {
+                app.set_history(history);
+            }
+        }
+        info!("Daemon went away");
+    });
+
+    for io_event in io_rx {
+        //the night light happens in the background all the time, it shouldn't hide the last error
+        let clears_status = !matches!(io_event, IoEvent::NightLight(_));
+        let result = match io_event {
+            IoEvent::Shutdown => break,
+            IoEvent::Initialize => initialize(&app, &mut client),
+            IoEvent::SaveProfile(name) => client
+                .call(&Request::SaveProfile { name })
+                .and_then(|_| client.profiles())
+                .map(|profiles| app.lock().set_profiles(profiles)),
+            io_event => to_request(&app, io_event).and_then(|request| client.call(&request)),
+        };
+        let mut app = app.lock();
+        match result {
+            Ok(()) if clears_status => app.set_status(None),
+            Ok(()) => {}
+            Err(err) => {
+                error!("Error in ipc::client::bridge: {}", err);
+                app.set_status(Some(err.to_string()));
+            }
+        }
+        app.loaded();
+    }
+    Ok(())
+}
+
+fn initialize(app: &Arc<FairMutex<App>>, client: &mut Client) -> Result<()> {
+    let disps = client.reports()?.iter().map(Disp::from).collect();
+    let profiles = client.profiles()?;
+    let history = client.history()?;
+    let mut app = app.lock();
+    app.initialize(disps);
+    app.set_profiles(profiles);
+    app.set_history(history);
+    info!("Application initialized from the daemon");
+    Ok(())
+}
+
+///The Request an IoEvent from the UI mirrors. Displays and groups are named by what the UI shows
+fn to_request(app: &Arc<FairMutex<App>>, io_event: IoEvent) -> Result<Request> {
+    let app = app.lock();
+    let disps = app.state.disps().unwrap_or_default();
+    let output = |i: usize| disps.get(i).map(|d| d.name.clone()).ok_or(DispError::NoSuchIndex(i));
+    Ok(match io_event {
+        IoEvent::Reload => Request::Reload,
+        IoEvent::DeviceIncrement(i, prop) => Request::Increment { output: output(i)?, prop },
+        IoEvent::DeviceDecrement(i, prop) => Request::Decrement { output: output(i)?, prop },
+        IoEvent::DeviceScale(i, prop, scale) => Request::Scale { output: output(i)?, prop, scale },
+        IoEvent::DeviceStep(i, prop, delta) => Request::Step { output: output(i)?, prop, delta },
+        IoEvent::DeviceFade(i, prop, value, duration) => {
+            Request::Set { output: output(i)?, prop, value, fade_ms: duration.as_millis() as u64 }
+        }
+        IoEvent::SetTemperature(i, kelvin) => Request::Temperature { output: i.map(output).transpose()?, kelvin },
+        IoEvent::NightLight(target) => Request::NightLight { kelvin: target.kelvin, brightness: target.brightness },
+        IoEvent::Sync(i) => Request::Sync { output: output(i)? },
+        IoEvent::Link(linked) => Request::Link { linked },
+        IoEvent::Group(g) => Request::Group { name: g.and_then(|g| app.state.groups().get(g)).map(|g| g.name.clone()) },
+        IoEvent::SaveProfile(name) => Request::SaveProfile { name },
+        IoEvent::ApplyProfile(name) => Request::ApplyProfile { name },
+        IoEvent::Restore => Request::Restore,
+        IoEvent::Undo => Request::Undo,
+        IoEvent::Redo => Request::Redo,
+        IoEvent::Initialize | IoEvent::Shutdown | IoEvent::Ipc(..) => {
+            return Err(DispError::Daemon(String::from("not a request")))
+        }
+    })
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+use log::{info, warn};
+use zbus::blocking::connection::Builder;
+use zbus::blocking::Connection;
+use zbus::fdo;
+use zbus::object_server::SignalEmitter;
+use super::{Mode, Request, Response};
+use crate::cli::Report;
+use crate::disp_mgr::disp::DispProp;
+use crate::disp_mgr::error::DispError;
+use crate::io::IoEvent;
+
+///Bus name and interface of the service
+pub const NAME: &str = "org.tuib.Displays";
+///Object the interface is at
+pub const PATH: &str = "/org/tuib/Displays";
+
+///Values of a display by prop name, in percent or Kelvin for temperature. Backlight and luminance
+///are left out when the display has none
+pub type Values = HashMap<String, u32>;
+
+///The org.tuib.Displays interface. Calls go to the IO thread as Requests, like the socket's do
+struct Service {
+    io_tx: Sender<IoEvent>,
+}
+
+impl Service {
+    ///Send a request to the IO thread and wait for the response. Error responses are errors
+    fn request(&self, request: Request) -> fdo::Result<Response> {
+        let gone = || fdo::Error::Failed(String::from("tuib is shutting down"));
+        let (reply_tx, reply_rx) = channel();
+        self.io_tx.send(IoEvent::Ipc(request, Mode::default(), reply_tx)).map_err(|_| gone())?;
+        match reply_rx.recv().map_err(|_| gone())? {
+            Response::Error { message } => Err(fdo::Error::Failed(message)),
+            response => Ok(response),
+        }
+    }
+
+    fn reports(&self) -> fdo::Result<Vec<Report>> {
+        match self.request(Request::List)? {
+            Response::Displays { displays } => Ok(displays),
+            response => Err(fdo::Error::Failed(format!("unexpected response {:?}", response))),
+        }
+    }
+}
+
+#[zbus::interface(name = "org.tuib.Displays")]
+impl Service {
+    ///Names of the displays
+    fn list(&self) -> fdo::Result<Vec<String>> {
+        Ok(self.reports()?.into_iter().map(|r| r.output).collect())
+    }
+
+    ///A value of a display
+    fn get(&self, output: &str, prop: &str) -> fdo::Result<u32> {
+        let prop = parse_prop(prop)?;
+        let reports = self.reports()?;
+        let report = reports
+            .iter()
+            .find(|r| r.output == output)
+            .ok_or_else(|| fdo::Error::Failed(DispError::NoSuchOutput(output.to_string()).to_string()))?;
+        values(report).get(&prop_name(prop)).copied().ok_or_else(|| {
+            fdo::Error::Failed(DispError::Unsupported(format!("{} has no {}", output, prop_name(prop))).to_string())
+        })
+    }
+
+    ///Set a value of a display, in percent or Kelvin for temperature
+    fn set(&self, output: &str, prop: &str, value: u32) -> fdo::Result<()> {
+        let output = output.to_string();
+        let request = match parse_prop(prop)? {
+            DispProp::Temperature => Request::Temperature {
+                output: Some(output),
+                kelvin: u16::try_from(value).map_err(|_| fdo::Error::InvalidArgs(format!("{}K is too hot", value)))?,
+            },
+            prop if value <= 100 => Request::Set { output, prop, value: value as u8, fade_ms: 0 },
+            _ => return Err(fdo::Error::InvalidArgs(format!("{} is over 100%", value))),
+        };
+        self.request(request).map(|_| ())
+    }
+
+    ///Move a value of a display by delta, in percent or Kelvin for temperature. Negative deltas
+    ///decrement
+    fn increment(&self, output: &str, prop: &str, delta: i32) -> fdo::Result<()> {
+        let request = Request::Step { output: output.to_string(), prop: parse_prop(prop)?, delta };
+        self.request(request).map(|_| ())
+    }
+
+    ///Sent with every display whenever any of them changed
+    #[zbus(signal)]
+    async fn changed(emitter: &SignalEmitter<'_>, displays: HashMap<String, Values>) -> zbus::Result<()>;
+}
+
+///A prop by the name the socket protocol uses, eg brightness or red
+fn parse_prop(prop: &str) -> fdo::Result<DispProp> {
+    serde_json::from_value(serde_json::Value::String(prop.to_string()))
+        .map_err(|_| fdo::Error::InvalidArgs(format!("no prop named {}", prop)))
+}
+
+fn prop_name(prop: DispProp) -> String {
+    match serde_json::to_value(prop) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => prop.to_string(),
+    }
+}
+
+fn values(report: &Report) -> Values {
+    let mut values = Values::from([
+        (prop_name(DispProp::Brightness), report.brightness as u32),
+        (prop_name(DispProp::R), report.gamma.r as u32),
+        (prop_name(DispProp::G), report.gamma.g as u32),
+        (prop_name(DispProp::B), report.gamma.b as u32),
+        (prop_name(DispProp::Temperature), report.temperature as u32),
+    ]);
+    if let Some(backlight) = report.backlight {
+        values.insert(prop_name(DispProp::Backlight), backlight as u32);
+    }
+    if let Some(luminance) = report.luminance {
+        values.insert(prop_name(DispProp::Luminance), luminance as u32);
+    }
+    values
+}
+
+///Export org.tuib.Displays on the bus builder connects to, and send Changed whenever a display
+///changes. The service goes away with the returned connection
+pub fn serve(builder: Builder, io_tx: Sender<IoEvent>) -> zbus::Result<Connection> {
+    let connection = builder.name(NAME)?.serve_at(PATH, Service { io_tx: io_tx.clone() })?.build()?;
+    let emitter = SignalEmitter::new(connection.inner(), PATH)?.into_owned();
+    let (changes_tx, changes_rx) = channel();
+    let _ = io_tx.send(IoEvent::Ipc(Request::Subscribe, Mode::default(), changes_tx));
+    thread::spawn(move || {
+        //the first is the displays as they were when subscribing, nothing changed yet
+        let changes = changes_rx.into_iter().filter_map(|response| match response {
+            Response::Changed { displays } => Some(displays),
+            _ => None,
+        });
+        for displays in changes.skip(1) {
+            let displays = displays.iter().map(|r| (r.output.clone(), values(r))).collect();
+            if let Err(e) = zbus::block_on(Service::changed(&emitter, displays)) {
+                warn!("Could not send Changed: {}", e);
+            }
+        }
+    });
+    info!("Exported {} on D-Bus", NAME);
+    Ok(connection)
+}
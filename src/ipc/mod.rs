@@ -0,0 +1,122 @@
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use crate::cli::Report;
+use crate::disp_mgr::history::History;
+use crate::disp_mgr::disp::DispProp;
+
+pub mod client;
+#[cfg(feature = "dbus")]
+pub mod dbus;
+pub mod server;
+
+///The protocol `tuib daemon` speaks on its Unix socket. Every message is one line of JSON. A client
+///writes a Request and gets one Response back, in order. Requests mirror IoEvent, with displays
+///and groups named instead of indexed:
+///
+///```text
+///{"cmd":"increment","output":"eDP-1","prop":"brightness"}   -> {"type":"ok"}
+///{"cmd":"set","output":"HDMI-1","prop":"red","value":90}     -> {"type":"ok"}
+///{"cmd":"set","output":"DP-9","prop":"brightness","value":5} -> {"type":"error","message":"no display named DP-9"}
+///{"cmd":"list"}                                              -> {"type":"displays","displays":[...]}
+///{"cmd":"subscribe"}                                         -> {"type":"ok"}, then a
+///                                  {"type":"changed","displays":[...]} whenever a display changes
+///```
+///
+///Props are brightness, backlight, luminance, temperature, red, green, blue or {"vcp":code}.
+///Displays are reported the way `tuib list --json` prints them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Request {
+    ///Look for connected or disconnected outputs
+    Reload,
+    Increment { output: String, prop: DispProp },
+    Decrement { output: String, prop: DispProp },
+    ///Move by delta, in Kelvin for temperature and percent for everything else
+    Step { output: String, prop: DispProp, delta: i32 },
+    ///Like the 0-9 keys, fading if the daemon is set to
+    Scale { output: String, prop: DispProp, scale: u8 },
+    ///Set a value, fading to it over fade_ms if given
+    Set {
+        output: String,
+        prop: DispProp,
+        value: u8,
+        #[serde(default)]
+        fade_ms: u64,
+    },
+    ///Set the color temperature of one display, or every display without an output
+    Temperature {
+        #[serde(default)]
+        output: Option<String>,
+        kelvin: u16,
+    },
+    ///Set every display to what the night light wants
+    NightLight {
+        kelvin: u16,
+        #[serde(default)]
+        brightness: Option<u8>,
+    },
+    ///Copy the settings of a display to every other display
+    Sync { output: String },
+    ///Send this connection's changes to every display, or stop doing that
+    Link { linked: bool },
+    ///This connection's changes to the leader of this group move its members. None for no group
+    Group {
+        #[serde(default)]
+        name: Option<String>,
+    },
+    SaveProfile { name: String },
+    ApplyProfile { name: String },
+    ///Put the displays back the way the daemon's saved state has them
+    Restore,
+    Undo,
+    Redo,
+    ///Save the state of every display and stop the daemon
+    Shutdown,
+    ///Reply with every display
+    List,
+    ///Reply with the names of the saved profiles
+    Profiles,
+    ///Reply with what can be undone and redone
+    History,
+    ///Reply, then send the displays every time they change until the connection closes
+    Subscribe,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    Ok,
+    Error { message: String },
+    Displays { displays: Vec<Report> },
+    Profiles { profiles: Vec<String> },
+    History { history: History },
+    ///Not a reply. Sent to subscribers when any display changed
+    Changed { displays: Vec<Report> },
+}
+
+///How the changes of one connection spread, from its last Link and Group requests. It's kept per
+///connection, so one client's linked mode or open group doesn't move displays for the others
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Mode {
+    pub linked: bool,
+    pub group: Option<String>,
+}
+
+impl Mode {
+    ///Follow a request of the connection, if it changes the mode
+    pub fn update(&mut self, request: &Request) {
+        match request {
+            Request::Link { linked } => self.linked = *linked,
+            Request::Group { name } => self.group = name.clone(),
+            _ => {}
+        }
+    }
+}
+
+///$XDG_RUNTIME_DIR/tuib.sock, or tuib-<uid>.sock in the temp dir without it
+pub fn socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("tuib.sock"),
+        _ => std::env::temp_dir().join(format!("tuib-{}.sock", unsafe { libc::getuid() })),
+    }
+}
@@ -0,0 +1,78 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+use log::{info, warn};
+use super::{Mode, Request, Response};
+use crate::io::IoEvent;
+
+///Listen on the socket at path and pass every Request on to the IO thread as IoEvent::Ipc. A
+///socket left behind by a daemon that died is replaced, one that still answers is an error
+pub fn listen(path: &Path, io_tx: Sender<IoEvent>) -> io::Result<()> {
+    let listener = match UnixListener::bind(path) {
+        Ok(listener) => listener,
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(io::ErrorKind::AddrInUse, format!("a daemon is already listening on {}", path.display())));
+            }
+            std::fs::remove_file(path)?;
+            UnixListener::bind(path)?
+        }
+        Err(e) => return Err(e),
+    };
+    info!("Listening on {}", path.display());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let io_tx = io_tx.clone();
+                    thread::spawn(move || serve(stream, io_tx));
+                }
+                Err(e) => warn!("Could not accept a connection: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+///Read Requests off one connection until it closes, keeping track of its Mode. Responses, and changes for subscribers, are
+///written by a thread of their own so the IO thread never waits on a slow client
+fn serve(stream: UnixStream, io_tx: Sender<IoEvent>) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(e) => {
+            warn!("Could not use a connection: {}", e);
+            return;
+        }
+    };
+    let (reply_tx, reply_rx) = channel::<Response>();
+    thread::spawn(move || {
+        for response in reply_rx {
+            let line = serde_json::to_string(&response).expect("responses always serialize");
+            if writeln!(writer, "{}", line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut mode = Mode::default();
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) if line.trim().is_empty() => continue,
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                mode.update(&request);
+                if io_tx.send(IoEvent::Ipc(request, mode.clone(), reply_tx.clone())).is_err() {
+                    break;
+                }
+            }
+            Err(e) => {
+                let _ = reply_tx.send(Response::Error { message: format!("bad request: {}", e) });
+            }
+        }
+    }
+}
@@ -11,9 +11,13 @@ use tui::backend::CrosstermBackend;
 use tui::Terminal;
 use crate::app::ui;
 pub mod app;
+pub mod cli;
+pub mod config;
 pub mod inputs;
 pub mod io;
+pub mod ipc;
 pub mod disp_mgr;
+pub mod night_light;
 
 pub fn start_ui(app: &Arc<FairMutex<App>>) -> Result<()> {
     //setup tui
@@ -24,6 +28,28 @@ pub fn start_ui(app: &Arc<FairMutex<App>>) -> Result<()> {
     terminal.clear()?;
     terminal.hide_cursor()?;
 
+    //don't leave the terminal in raw mode if anything panics
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        default_hook(info);
+    }));
+
+    let result = run_ui(&mut terminal, app);
+
+    terminal.show_cursor()?;
+    terminal.clear()?;
+    crossterm::terminal::disable_raw_mode()?;
+    result
+}
+
+///Put the terminal back the way it was, ignoring errors since this runs while panicking
+fn restore_terminal() {
+    let _ = crossterm::terminal::disable_raw_mode();
+    let _ = crossterm::execute!(stdout(), crossterm::cursor::Show);
+}
+
+fn run_ui<B: tui::backend::Backend>(terminal: &mut Terminal<B>, app: &Arc<FairMutex<App>>) -> Result<()> {
     // todo: when tick rate is raised (eg 200), weird stuff happens when holding keys. test it
     let tick_rate = Duration::from_millis(10);
     let mut events = Events::new(tick_rate);
@@ -51,8 +77,5 @@ pub fn start_ui(app: &Arc<FairMutex<App>>) -> Result<()> {
         }
     }
 
-    terminal.show_cursor()?;
-    terminal.clear()?;
-    crossterm::terminal::disable_raw_mode()?;
     Ok(())
 }
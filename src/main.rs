@@ -1,34 +1,236 @@
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 use parking_lot::FairMutex;
+use clap::Parser;
 use eyre::Result;
-use log::LevelFilter;
+use log::{error, LevelFilter};
 use tuib::app::App;
+use tuib::cli::{Cli, Command, Displays};
+use tuib::config::Config;
+use tuib::disp_mgr::backend::DisplayBackend;
+use tuib::disp_mgr::backlight::Backlight;
+use tuib::disp_mgr::ddc::Ddc;
+use tuib::disp_mgr::profile::{Profiles, State};
+use tuib::disp_mgr::error::{DispError, Result as DispResult};
+use tuib::disp_mgr::{backend, DispMgr};
 use tuib::io::handler::IoHandler;
+use tuib::io::hotplug;
 use tuib::io::IoEvent;
+use tuib::ipc::{self, client::Client, Request};
+use tuib::night_light::{self, NightLight};
 use tuib::start_ui;
 
 fn main() -> Result<()> {
+    //bad arguments print usage and exit with 2 before anything is touched
+    let cli = Cli::parse();
+
     //channel for passing messages from UI to IO thread
     let (sync_io_tx, sync_io_rx) = std::sync::mpsc::channel::<IoEvent>();
 
+    let config = Config::load()?;
+
+    //the daemon has no debug tab to show logs in
+    if matches!(cli.command, Some(Command::Daemon(_))) {
+        log::set_logger(&StderrLogger).unwrap();
+        log::set_max_level(LevelFilter::Info);
+    } else {
+        //init tui_logger. may remove later
+        tui_logger::init_logger(LevelFilter::Debug).unwrap();
+        tui_logger::set_default_level(log::LevelFilter::Debug);
+    }
+
+    //a running daemon owns the displays, so the TUI and commands go through it
+    let socket = ipc::socket_path();
+    if let Some(Command::Status(status)) = &cli.command {
+        exit(status.run(&socket));
+    }
+    if !matches!(cli.command, Some(Command::Daemon(_))) {
+        if let Ok(mut client) = Client::connect(&socket) {
+            if let Some(name) = &cli.profile {
+                exit(client.call(&Request::ApplyProfile { name: name.clone() }));
+            }
+            match &cli.command {
+                Some(Command::Restore) => exit(client.call(&Request::Restore)),
+                Some(Command::NightLight) => exit(Err(DispError::Daemon(String::from(
+                    "tuib daemon is running, it follows the night light schedule itself",
+                )))),
+                _ => {}
+            }
+            run_command(&cli.command, &mut client);
+            if cli.command.is_none() {
+                return start_client_ui(&config, socket);
+            }
+        }
+    }
+
     //create and clone uninitialzed app
-    let app = Arc::new(FairMutex::new(App::new(sync_io_tx))); //for io thread
+    let hotplug_tx = sync_io_tx.clone();
+    let app = Arc::new(FairMutex::new(App::with_config(sync_io_tx, &config))); //for io thread
     let app_ui = Arc::clone(&app);                        //for ui(main) thread
 
-    //init tui_logger. may remove later
-    tui_logger::init_logger(LevelFilter::Debug).unwrap();
-    tui_logger::set_default_level(log::LevelFilter::Debug);
+    //find displays before starting anything so a broken backend fails early. Quick commands don't
+    //wait for monitors to answer over DDC/CI unless they change their luminance
+    let ddc = match &cli.command {
+        Some(command) if cli.profile.is_none() && !command.needs_ddc() => Ddc::disabled(),
+        _ => Ddc::default(),
+    };
+    let mut disp_mgr = DispMgr::with_hardware(backend::detect(), Backlight::default(), ddc)?;
 
-    // IO thread. just listen for instructions from UI thread
-    std::thread::spawn(move || {
-        let mut handler = IoHandler::new(app);
-        while let Ok(io_event) = sync_io_rx.recv() {
-            handler.handle_io_event(io_event);
+    //apply a profile and leave
+    if let Some(name) = &cli.profile {
+        let profiles = match Profiles::path() {
+            Some(path) => Profiles::load(&path)?,
+            None => Profiles::default(),
+        };
+        disp_mgr.apply_profile(profiles.get(name)?)?;
+        return Ok(());
+    }
+
+    //commands run without the TUI. errors exit with 1
+    run_command(&cli.command, &mut disp_mgr);
+    match cli.command {
+        //run only the night light schedule
+        Some(Command::NightLight) => {
+            let night_light = config
+                .night_light
+                .clone()
+                .ok_or_else(|| eyre::eyre!("night-light needs a [night_light] table in the config"))?;
+            night_light::run(&NightLight::new(night_light), &mut disp_mgr);
+        }
+        //put the displays back the way tuib last left them
+        Some(Command::Restore) => {
+            if let Some(path) = State::path() {
+                disp_mgr.apply_profile(&State::load(&path)?.displays)?;
+            }
+            return Ok(());
+        }
+        //the IO thread without a UI, taking IoEvents from the socket instead
+        #[cfg_attr(not(feature = "dbus"), allow(unused_variables))]
+        Some(Command::Daemon(daemon)) => {
+            ipc::server::listen(&socket, hotplug_tx.clone())?;
+            #[cfg(feature = "dbus")]
+            let _bus = match daemon.dbus {
+                true => Some(ipc::dbus::serve(zbus::blocking::connection::Builder::session()?, hotplug_tx.clone())?),
+                false => None,
+            };
+            hotplug::start(disp_mgr.backend(), hotplug_tx);
+            //the UI moves the night light on every tick, here nothing else would
+            let app_ticks = Arc::clone(&app);
+            thread::spawn(move || loop {
+                thread::sleep(Duration::from_secs(1));
+                app_ticks.lock().update_on_tick();
+            });
+            app.lock().dispatch(IoEvent::Initialize);
+            io_handler(app, disp_mgr, &config).run(sync_io_rx);
+            let _ = std::fs::remove_file(&socket);
+            return Ok(());
         }
+        _ => {}
+    }
+    hotplug::start(disp_mgr.backend(), hotplug_tx);
+
+    // IO thread. just listen for instructions from UI thread
+    let io_thread = std::thread::spawn(move || {
+        io_handler(app, disp_mgr, &config).run(sync_io_rx);
     });
 
     //ui(main) thread
-    start_ui(&app_ui)?;
+    let result = start_ui(&app_ui);
+
+    //let the IO thread save the state before leaving
+    app_ui.lock().dispatch(IoEvent::Shutdown);
+    let _ = io_thread.join();
+
+    result
+}
+
+///An IoHandler set up from the config and profiles.toml
+fn io_handler<B: DisplayBackend>(app: Arc<FairMutex<App>>, disp_mgr: DispMgr<B>, config: &Config) -> IoHandler<B> {
+    let (profiles, profiles_path) = load_profiles(&app);
+    let mut handler = IoHandler::new(app, disp_mgr);
+    handler.set_fade(config.fade());
+    handler.set_groups(config.groups.clone());
+    handler.set_profiles(profiles, profiles_path);
+    handler.set_state_path(State::path());
+    handler
+}
+
+///Profiles for the TUI and the daemon, and where to save them. A profiles.toml that can't be
+///loaded is shown in the status line and not saved to, so the profiles in it aren't lost
+fn load_profiles(app: &Arc<FairMutex<App>>) -> (Profiles, Option<PathBuf>) {
+    let path = match Profiles::path() {
+        Some(path) => path,
+        None => return (Profiles::default(), None),
+    };
+    match Profiles::load(&path) {
+        Ok(profiles) => (profiles, Some(path)),
+        Err(e) => {
+            error!("Error loading profiles: {}", e);
+            app.lock().set_status(Some(e.to_string()));
+            (Profiles::default(), None)
+        }
+    }
+}
+
+///Run the TUI against the daemon listening at socket
+fn start_client_ui(config: &Config, socket: PathBuf) -> Result<()> {
+    let (io_tx, io_rx) = std::sync::mpsc::channel::<IoEvent>();
+    let app = Arc::new(FairMutex::new(App::with_config(io_tx, config)));
+    //the daemon follows the night light schedule, two of them would fight over the displays
+    app.lock().set_drives_night_light(false);
+    let app_bridge = Arc::clone(&app);
+    let bridge = thread::spawn(move || {
+        if let Err(e) = ipc::client::bridge(Arc::clone(&app_bridge), Path::new(&socket), io_rx) {
+            app_bridge.lock().set_status(Some(e.to_string()));
+        }
+    });
+
+    let result = start_ui(&app);
+
+    //the daemon keeps running, this only stops the bridge
+    app.lock().dispatch(IoEvent::Shutdown);
+    let _ = bridge.join();
+
+    result
+}
 
-    Ok(())
+///Run a command that only needs displays and leave. Other commands are left to the caller
+fn run_command(command: &Option<Command>, displays: &mut dyn Displays) {
+    match command {
+        Some(Command::Set(set)) => exit(set.run(displays)),
+        Some(Command::Inc(step)) => exit(step.run(displays, true)),
+        Some(Command::Dec(step)) => exit(step.run(displays, false)),
+        Some(Command::Scale(scale)) => exit(scale.run(displays)),
+        Some(Command::List(list)) => exit(list.run(displays).map(|out| println!("{}", out))),
+        Some(Command::Get(get)) => exit(get.run(displays).map(|out| println!("{}", out))),
+        _ => {}
+    }
+}
+
+///Logs to stderr, where the daemon's service manager picks them up
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        eprintln!("{} {}: {}", record.level(), record.target(), record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+///Leave after a command. Scripts get 0, or 1 and the error on stderr
+fn exit(result: DispResult<()>) -> ! {
+    match result {
+        Ok(()) => std::process::exit(0),
+        Err(e) => {
+            eprintln!("tuib: {}", e);
+            std::process::exit(1)
+        }
+    }
 }
@@ -0,0 +1,283 @@
+use std::f64::consts::PI;
+use std::fmt::{self, Display};
+use serde::Deserialize;
+use crate::disp_mgr::backend::DisplayBackend;
+use crate::disp_mgr::disp::DispProp;
+use crate::disp_mgr::error::Result;
+use crate::disp_mgr::{temperature, DispMgr};
+
+///The [night_light] table of the config
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NightLightConfig {
+    ///Degrees, north is positive
+    pub latitude: f64,
+    ///Degrees, east is positive
+    pub longitude: f64,
+    #[serde(default = "default_day_temperature")]
+    pub day_temperature: u16,
+    #[serde(default = "default_night_temperature")]
+    pub night_temperature: u16,
+    #[serde(default = "default_day_brightness")]
+    pub day_brightness: u8,
+    ///Brightness is left alone unless this is set
+    #[serde(default)]
+    pub night_brightness: Option<u8>,
+    ///How long the change from day to night takes. Sunset and sunrise are in the middle of it
+    #[serde(default = "default_transition_minutes")]
+    pub transition_minutes: u32,
+}
+
+fn default_day_temperature() -> u16 {
+    temperature::NEUTRAL
+}
+
+fn default_night_temperature() -> u16 {
+    3400
+}
+
+fn default_day_brightness() -> u8 {
+    100
+}
+
+fn default_transition_minutes() -> u32 {
+    60
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Day,
+    Sunset,
+    Night,
+    Sunrise,
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Phase::Day => write!(f, "Day"),
+            Phase::Sunset => write!(f, "Sunset"),
+            Phase::Night => write!(f, "Night"),
+            Phase::Sunrise => write!(f, "Sunrise"),
+        }
+    }
+}
+
+///What every display should be set to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Target {
+    pub kelvin: u16,
+    pub brightness: Option<u8>,
+}
+
+///Where the schedule is at a point in time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Status {
+    pub phase: Phase,
+    pub target: Target,
+    ///Unix time the phase ends. None near the poles when the sun doesn't rise or set
+    pub next: Option<i64>,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Night light: {} {}K", self.phase, self.target.kelvin)?;
+        if let Some(next) = self.next {
+            write!(f, " until {}", local_time(next))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Event {
+    Rise(i64),
+    Set(i64),
+}
+
+impl Event {
+    fn time(&self) -> i64 {
+        match self {
+            Event::Rise(t) | Event::Set(t) => *t,
+        }
+    }
+}
+
+///Unix time of the Julian date 0
+const JULIAN_UNIX_EPOCH: f64 = 2440587.5;
+const J2000: f64 = 2451545.0;
+
+///Sunrise and sunset of the solar day n days after J2000, as unix times. Err(true) if the sun never
+///sets that day and Err(false) if it never rises. See https://en.wikipedia.org/wiki/Sunrise_equation
+pub fn sun_times(n: f64, latitude: f64, longitude: f64) -> std::result::Result<(i64, i64), bool> {
+    let rad = PI / 180.0;
+    let mean_noon = n - longitude / 360.0;
+    let anomaly = (357.5291 + 0.98560028 * mean_noon).rem_euclid(360.0) * rad;
+    let center = 1.9148 * anomaly.sin() + 0.02 * (2.0 * anomaly).sin() + 0.0003 * (3.0 * anomaly).sin();
+    let ecliptic = (anomaly / rad + center + 180.0 + 102.9372).rem_euclid(360.0) * rad;
+    let transit = J2000 + mean_noon + 0.0053 * anomaly.sin() - 0.0069 * (2.0 * ecliptic).sin();
+    let declination = (ecliptic.sin() * (23.4397 * rad).sin()).asin();
+    let lat = latitude * rad;
+    let cos_hour_angle =
+        ((-0.833 * rad).sin() - lat.sin() * declination.sin()) / (lat.cos() * declination.cos());
+    if cos_hour_angle < -1.0 {
+        return Err(true);
+    }
+    if cos_hour_angle > 1.0 {
+        return Err(false);
+    }
+    let half_day = cos_hour_angle.acos() / rad / 360.0;
+    let unix = |julian: f64| ((julian - JULIAN_UNIX_EPOCH) * 86400.0).round() as i64;
+    Ok((unix(transit - half_day), unix(transit + half_day)))
+}
+
+pub struct NightLight {
+    config: NightLightConfig,
+}
+
+impl NightLight {
+    pub fn new(config: NightLightConfig) -> Self {
+        NightLight { config }
+    }
+
+    pub fn config(&self) -> &NightLightConfig {
+        &self.config
+    }
+
+    ///Where the schedule is at unix time now
+    pub fn status(&self, now: i64) -> Status {
+        let half = self.config.transition_minutes as i64 * 30;
+        let events = self.events(now);
+
+        //in the middle of a transition
+        for event in &events {
+            let start = event.time() - half;
+            let end = event.time() + half;
+            if half > 0 && start <= now && now < end {
+                let progress = (now - start) as f64 / (end - start) as f64;
+                let (phase, night) = match event {
+                    Event::Set(_) => (Phase::Sunset, progress),
+                    Event::Rise(_) => (Phase::Sunrise, 1.0 - progress),
+                };
+                return Status { phase, target: self.target(night), next: Some(end) };
+            }
+        }
+
+        //otherwise it's whatever the last event made it, until the next one starts
+        let last = events.iter().rev().find(|e| e.time() <= now);
+        let next = events.iter().find(|e| e.time() > now).map(|e| e.time() - half);
+        let night = match last {
+            Some(Event::Set(_)) => true,
+            Some(Event::Rise(_)) => false,
+            //polar day or night
+            None => self.polar_night(now),
+        };
+        match night {
+            true => Status { phase: Phase::Night, target: self.target(1.0), next },
+            false => Status { phase: Phase::Day, target: self.target(0.0), next },
+        }
+    }
+
+    ///Settings for a point between day (0) and night (1)
+    fn target(&self, night: f64) -> Target {
+        let lerp = |day: f64, night_val: f64| day + (night_val - day) * night;
+        let kelvin = lerp(self.config.day_temperature as f64, self.config.night_temperature as f64);
+        let brightness = self
+            .config
+            .night_brightness
+            .map(|b| lerp(self.config.day_brightness as f64, b as f64).round() as u8);
+        Target { kelvin: kelvin.round() as u16, brightness }
+    }
+
+    ///Sunrises and sunsets of the days around now, in order
+    fn events(&self, now: i64) -> Vec<Event> {
+        let today = self.day_number(now);
+        let mut events = vec![];
+        for day in -2..=2 {
+            let n = today + day as f64;
+            if let Ok((rise, set)) = sun_times(n, self.config.latitude, self.config.longitude) {
+                events.push(Event::Rise(rise));
+                events.push(Event::Set(set));
+            }
+        }
+        events.sort_by_key(Event::time);
+        events
+    }
+
+    ///Whether it's a day the sun doesn't rise
+    fn polar_night(&self, now: i64) -> bool {
+        sun_times(self.day_number(now), self.config.latitude, self.config.longitude) == Err(false)
+    }
+
+    fn day_number(&self, now: i64) -> f64 {
+        let julian = now as f64 / 86400.0 + JULIAN_UNIX_EPOCH;
+        (julian - J2000 + 0.0008).round()
+    }
+}
+
+///Set every display to target
+pub fn apply<B: DisplayBackend>(disp_mgr: &mut DispMgr<B>, target: Target) -> Result<()> {
+    let mut result = Ok(());
+    for i in 0..disp_mgr.get_num_disps() {
+        if let Err(e) = disp_mgr.set_temperature_by_index(i, target.kelvin) {
+            result = result.and(Err(e));
+        }
+        if let Some(brightness) = target.brightness {
+            if let Err(e) = disp_mgr.set_value_by_index(i, DispProp::Brightness, brightness) {
+                result = result.and(Err(e));
+            }
+        }
+    }
+    result
+}
+
+///Run the schedule without the TUI, forever. Outputs that come and go are picked up as it goes
+pub fn run<B: DisplayBackend>(night_light: &NightLight, disp_mgr: &mut DispMgr<B>) {
+    let mut applied: Option<Target> = None;
+    let mut phase = None;
+    loop {
+        if let Err(e) = disp_mgr.reload() {
+            eprintln!("tuib: {}", e);
+        }
+        let now = now();
+        let status = night_light.status(now);
+        if phase != Some(status.phase) {
+            println!("{}", status);
+            phase = Some(status.phase);
+        }
+        //reload may have brought new displays, so apply every time. it's only every few seconds
+        if let Err(e) = apply(disp_mgr, status.target) {
+            if applied != Some(status.target) {
+                eprintln!("tuib: {}", e);
+            }
+        }
+        applied = Some(status.target);
+
+        //transitions move a little every few seconds, otherwise wait for the next one
+        let wait = match (status.phase, status.next) {
+            (Phase::Sunset | Phase::Sunrise, _) => 5,
+            (_, Some(next)) => (next - now).clamp(1, 60),
+            (_, None) => 60,
+        };
+        std::thread::sleep(std::time::Duration::from_secs(wait as u64));
+    }
+}
+
+///Seconds since the unix epoch
+pub fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+///Format a unix time as HH:MM in the local timezone
+pub fn local_time(unix: i64) -> String {
+    let time = unix as libc::time_t;
+    //SAFETY: localtime_r only writes to tm, which is a plain C struct
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return String::from("??:??");
+    }
+    format!("{:02}:{:02}", tm.tm_hour, tm.tm_min)
+}
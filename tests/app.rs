@@ -0,0 +1,437 @@
+mod common;
+
+use common::{Harness, NEUTRAL};
+use tuib::app::state::Tab;
+use tuib::app::AppReturn;
+use tuib::config::Config;
+use tuib::disp_mgr::backend::Mock;
+use tuib::disp_mgr::profile::{Profiles, State};
+use tuib::disp_mgr::disp::{DispProp, Gamma};
+use tuib::disp_mgr::error::DispError;
+use std::time::{Duration, Instant};
+use tuib::inputs::key::Key;
+use tuib::io::IoEvent;
+
+#[test]
+fn initialize_reads_every_output() {
+    let h = Harness::two_outputs();
+    let state = h.app.lock().state.clone();
+    assert_eq!(state.num_disps(), Some(2));
+    assert_eq!(state.tab_index(), Some(0));
+    assert_eq!(state.focused_prop(), Some(DispProp::Brightness));
+
+    let disps = state.disps().unwrap();
+    assert_eq!(disps[0].brightness, 80);
+    assert_eq!(disps[1].gamma.g, 80);
+    //nothing is applied just by starting up
+    assert!(h.mock.applied().is_empty());
+}
+
+#[test]
+fn increment_and_decrement_apply_to_focused_display() {
+    let mut h = Harness::two_outputs();
+    h.press_all(&[Key::Char('l'), Key::Right]);
+    assert_eq!(h.handler.disp_mgr().disps[0].brightness, 82);
+    assert_eq!(h.mock.output("eDP-1").unwrap().brightness, 82);
+
+    h.press(Key::Char('L'));
+    h.press(Key::Left);
+    assert_eq!(h.mock.output("HDMI-1").unwrap().brightness, 99);
+    assert_eq!(h.mock.output("eDP-1").unwrap().brightness, 82);
+    assert_eq!(h.mock.applied().len(), 3);
+}
+
+#[test]
+fn scale_sets_focused_prop() {
+    let mut h = Harness::two_outputs();
+    h.press_all(&[Key::Char('j'), Key::Char('j'), Key::Char('5')]);
+    let out = h.mock.output("eDP-1").unwrap();
+    assert_eq!(out.gamma.r, 50);
+    assert_eq!(out.brightness, 80);
+
+    h.press(Key::Char('0'));
+    assert_eq!(h.mock.output("eDP-1").unwrap().gamma.r, 100);
+}
+
+#[test]
+fn values_stay_in_range() {
+    let mut h = Harness::new(Mock::new().with_output("DP-1", 2, NEUTRAL));
+    h.press_all(&[Key::Left, Key::Left, Key::Left]);
+    assert_eq!(h.mock.output("DP-1").unwrap().brightness, 1);
+
+    h.press_all(&[Key::Down, Key::Down]);
+    h.press_all(&[Key::Right, Key::Right]);
+    assert_eq!(h.mock.output("DP-1").unwrap().gamma.r, 100);
+}
+
+#[test]
+fn temperature_drives_gamma() {
+    let mut h = Harness::new(Mock::new().with_output("DP-1", 100, NEUTRAL));
+    h.press(Key::Down);
+    assert_eq!(h.app.lock().state.focused_prop(), Some(DispProp::Temperature));
+
+    h.press(Key::Left);
+    let disp = &h.handler.disp_mgr().disps[0];
+    assert_eq!(disp.temperature, 6400);
+    assert_eq!(disp.gamma.r, 100);
+    assert!(disp.gamma.b < 100);
+
+    //scale goes along the 1000K-10000K gauge, the warm end has almost no blue
+    h.press(Key::Char('1'));
+    let out = h.mock.output("DP-1").unwrap();
+    assert_eq!(h.handler.disp_mgr().disps[0].temperature, 1900);
+    assert!(out.gamma.r > out.gamma.g && out.gamma.g > out.gamma.b);
+}
+
+#[test]
+fn focus_and_tabs_wrap() {
+    let mut h = Harness::two_outputs();
+    h.press(Key::Up);
+    assert_eq!(h.app.lock().state.focused_prop(), Some(DispProp::B));
+    h.press(Key::Down);
+    assert_eq!(h.app.lock().state.focused_prop(), Some(DispProp::Brightness));
+
+    //the last tab is the debug tab
+    h.press(Key::Char('H'));
+    assert_eq!(h.app.lock().state.tab_index(), Some(2));
+    h.press(Key::Char('.'));
+    assert_eq!(h.app.lock().state.tab_index(), Some(0));
+}
+
+#[test]
+fn nothing_is_applied_on_debug_tab() {
+    let mut h = Harness::two_outputs();
+    h.press_all(&[Key::Char('<'), Key::Right, Key::Char('7')]);
+    assert!(h.mock.applied().is_empty());
+}
+
+#[test]
+fn quit() {
+    let mut h = Harness::two_outputs();
+    assert_eq!(h.press(Key::Char('x')), AppReturn::Continue);
+    assert_eq!(h.press(Key::Char('q')), AppReturn::Exit);
+}
+
+#[test]
+fn draw_shows_tabs_and_gauges() {
+    let mut h = Harness::new(
+        Mock::new()
+            .with_output("eDP-1", 100, NEUTRAL)
+            .with_output("DP-2", 100, Gamma { r: 100, g: 100, b: 40 }),
+    );
+    let screen = h.screen();
+    assert!(screen.contains("eDP-1"));
+    assert!(screen.contains("DP-2"));
+    assert!(screen.contains("Debug"));
+    assert!(screen.contains("[Brightness]"));
+    assert!(screen.contains("Red"));
+
+    h.press(Key::Down);
+    assert!(h.screen().contains("[Temperature (6500K)]"));
+    h.press(Key::Down);
+    let screen = h.screen();
+    assert!(screen.contains("[Red]"));
+    assert!(!screen.contains("[Brightness]"));
+}
+
+#[test]
+fn backend_errors_show_in_status_line() {
+    let mut h = Harness::two_outputs();
+    h.mock.set_fail(true);
+    h.press(Key::Right);
+    let status = h.app.lock().status().map(str::to_string);
+    assert_eq!(status.as_deref(), Some("command failed: mock backend told to fail"));
+    //the failed change isn't kept
+    assert_eq!(h.handler.disp_mgr().disps[0].brightness, 80);
+    assert!(h.screen().contains("Error: command failed"));
+
+    //the next change that works clears it
+    h.mock.set_fail(false);
+    h.press(Key::Right);
+    assert_eq!(h.app.lock().status(), None);
+    assert_eq!(h.mock.output("eDP-1").unwrap().brightness, 81);
+}
+
+#[test]
+fn small_terminal_does_not_panic() {
+    let h = Harness::two_outputs();
+    let screen = common::buffer_text(&h.draw(40, 10));
+    assert!(screen.contains("Require width >= 52"));
+}
+
+#[test]
+fn hotplug_adds_and_removes_tabs() {
+    let mut h = Harness::two_outputs();
+    //change the external monitor and stay on its tab
+    h.press_all(&[Key::Char('L'), Key::Left]);
+    assert_eq!(h.handler.disp_mgr().disps[1].brightness, 99);
+
+    h.mock.connect("DP-2", 60, Gamma { r: 100, g: 95, b: 90 });
+    h.drain();
+    let state = h.app.lock().state.clone();
+    assert_eq!(state.num_disps(), Some(3));
+    let disps = state.disps().unwrap();
+    assert_eq!(disps[2].name, "DP-2");
+    assert_eq!(disps[2].brightness, 60);
+    //displays that stayed keep their values
+    assert_eq!(disps[1].brightness, 99);
+    assert!(h.screen().contains("DP-2"));
+
+    //unplugging the display before the focused one keeps the same display focused
+    h.mock.disconnect("eDP-1");
+    h.drain();
+    let state = h.app.lock().state.clone();
+    assert_eq!(state.num_disps(), Some(2));
+    assert_eq!(state.tab_index(), Some(0));
+    assert_eq!(state.disps().unwrap()[0].name, "HDMI-1");
+
+    //unplugging the focused display moves to the debug tab
+    h.mock.disconnect("HDMI-1");
+    h.drain();
+    let state = h.app.lock().state.clone();
+    assert_eq!(state.num_disps(), Some(1));
+    assert_eq!(state.tab_index(), Some(1));
+}
+
+#[test]
+fn reload_key_reloads() {
+    let mut h = Harness::new(Mock::new().with_output("eDP-1", 100, NEUTRAL));
+    h.mock.set_fail(true);
+    h.press(Key::Char('r'));
+    assert!(h.app.lock().status().is_some());
+
+    h.mock.set_fail(false);
+    h.press(Key::Char('r'));
+    assert_eq!(h.app.lock().state.num_disps(), Some(1));
+}
+
+#[test]
+fn preset_menu_applies_temperature() {
+    let mut h = Harness::two_outputs();
+    let other = h.handler.disp_mgr().disps[1].temperature;
+    h.press(Key::Char('t'));
+    assert_eq!(h.app.lock().preset_menu(), Some(0));
+    let screen = h.screen();
+    assert!(screen.contains("Candle"));
+    assert!(screen.contains("Overcast"));
+
+    //menu keys don't reach the gauges while it is open
+    h.press_all(&[Key::Down, Key::Right]);
+    assert!(h.mock.applied().is_empty());
+    h.press(Key::Enter);
+    assert_eq!(h.app.lock().preset_menu(), None);
+    assert_eq!(h.handler.disp_mgr().disps[0].temperature, 2700);
+    assert_eq!(h.handler.disp_mgr().disps[1].temperature, other);
+
+    h.press_all(&[Key::Char('t'), Key::Up, Key::Char('a')]);
+    let last = h.app.lock().presets().last().unwrap().kelvin;
+    assert!(h.handler.disp_mgr().disps.iter().all(|d| d.temperature == last));
+
+    h.press_all(&[Key::Char('t'), Key::Esc]);
+    assert_eq!(h.app.lock().preset_menu(), None);
+}
+
+#[test]
+fn scale_fades_and_input_cancels_the_fade() {
+    let mut h = Harness::two_outputs();
+    h.handler.set_fade(Duration::from_millis(300));
+    let start = Instant::now();
+    h.press(Key::Char('9'));
+    assert!(h.handler.is_fading());
+    assert_eq!(h.mock.output("eDP-1").unwrap().brightness, 80);
+
+    h.handler.tick(start + Duration::from_millis(150));
+    let halfway = h.mock.output("eDP-1").unwrap().brightness;
+    assert!(halfway > 80 && halfway < 90);
+    h.handler.tick(start + Duration::from_secs(1));
+    assert_eq!(h.mock.output("eDP-1").unwrap().brightness, 90);
+    assert!(!h.handler.is_fading());
+    assert_eq!(h.app.lock().state.disps().unwrap()[0].brightness, 90);
+
+    //a key on the same prop takes over from where the fade got to
+    h.press_all(&[Key::Char('1'), Key::Right]);
+    assert!(!h.handler.is_fading());
+    assert_eq!(h.mock.output("eDP-1").unwrap().brightness, 91);
+
+    //commands can bring their own duration
+    h.app.lock().dispatch(IoEvent::DeviceFade(1, DispProp::R, 40, Duration::ZERO));
+    h.drain();
+    assert_eq!(h.mock.output("HDMI-1").unwrap().gamma.r, 40);
+}
+
+#[test]
+fn sync_copies_focused_display_to_the_others() {
+    let mut h = Harness::two_outputs();
+    h.press_all(&[Key::Char('L'), Key::Char('s')]);
+    let edp = h.mock.output("eDP-1").unwrap();
+    assert_eq!(edp.brightness, 100);
+    assert_eq!((edp.gamma.r, edp.gamma.g, edp.gamma.b), (90, 80, 70));
+    let disps = h.app.lock().state.disps().unwrap();
+    assert_eq!(disps[0].temperature, disps[1].temperature);
+}
+
+#[test]
+fn linked_mode_fans_out_changes() {
+    let mut h = Harness::two_outputs();
+    assert!(!h.screen().contains("[linked]"));
+    h.press_all(&[Key::Char('S'), Key::Left, Key::Char('5')]);
+    assert!(h.app.lock().linked());
+    assert!(h.screen().contains("tuib [linked]"));
+    assert_eq!(h.mock.output("eDP-1").unwrap().brightness, 50);
+    assert_eq!(h.mock.output("HDMI-1").unwrap().brightness, 50);
+    assert_eq!(h.mock.applied().len(), 4);
+
+    h.press_all(&[Key::Char('S'), Key::Right]);
+    assert_eq!(h.mock.output("eDP-1").unwrap().brightness, 51);
+    assert_eq!(h.mock.output("HDMI-1").unwrap().brightness, 50);
+}
+
+#[test]
+fn group_tab_moves_members_relative_to_leader() {
+    let config = Config::parse(
+        r#"
+        [[groups]]
+        name = "desk"
+        leader = "eDP-1"
+        members = [{ output = "HDMI-1", multiplier = 0.8, offset = -5 }]
+
+        [[groups]]
+        name = "away"
+        leader = "DP-9"
+        "#,
+    )
+    .unwrap();
+    let mock = Mock::new().with_output("eDP-1", 80, NEUTRAL).with_output("HDMI-1", 100, NEUTRAL);
+    let mut h = Harness::with_config(mock, &config);
+
+    //groups are tabs between the displays and debug
+    h.press_all(&[Key::Char('L'), Key::Char('L')]);
+    assert_eq!(h.app.lock().state.tab(), Some(Tab::Group(0)));
+    let screen = h.screen();
+    assert!(screen.contains("desk"));
+    assert!(screen.contains("eDP-1 leads HDMI-1 x0.8 -5"));
+
+    h.press(Key::Right);
+    assert_eq!(h.mock.output("eDP-1").unwrap().brightness, 81);
+    assert_eq!(h.mock.output("HDMI-1").unwrap().brightness, 60);
+    h.press(Key::Char('5'));
+    assert_eq!(h.mock.output("HDMI-1").unwrap().brightness, 35);
+
+    //the leader on its own tab doesn't move the group
+    h.press_all(&[Key::Char('H'), Key::Char('H'), Key::Right]);
+    assert_eq!(h.mock.output("eDP-1").unwrap().brightness, 51);
+    assert_eq!(h.mock.output("HDMI-1").unwrap().brightness, 35);
+
+    h.press_all(&[Key::Char('H'), Key::Char('H')]);
+    assert_eq!(h.app.lock().state.tab(), Some(Tab::Group(1)));
+    assert!(h.screen().contains("DP-9 is not connected"));
+    let applied = h.mock.applied().len();
+    h.press(Key::Right);
+    assert_eq!(h.mock.applied().len(), applied);
+    h.press(Key::Char('L'));
+    assert_eq!(h.app.lock().state.tab(), Some(Tab::Debug));
+}
+
+#[test]
+fn profiles_are_saved_and_applied_from_the_menu() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("profiles.toml");
+    let mut h = Harness::two_outputs();
+    h.handler.set_profiles(Profiles::default(), Some(path.clone()));
+
+    //with no profiles the only row saves a new one
+    h.press_all(&[Key::Char('p'), Key::Enter]);
+    for c in "coding".chars() {
+        h.press(Key::Char(c));
+    }
+    assert!(h.screen().contains("Name: coding_"));
+    h.press(Key::Enter);
+    assert_eq!(h.app.lock().profile_menu(), None);
+    assert_eq!(h.app.lock().profiles(), ["coding".to_string()]);
+    assert_eq!(Profiles::load(&path).unwrap().names(), ["coding"]);
+
+    h.press_all(&[Key::Char('3'), Key::Char('L'), Key::Char('j'), Key::Char('j'), Key::Char('1')]);
+    assert_eq!(h.mock.output("eDP-1").unwrap().brightness, 30);
+    assert_eq!(h.mock.output("HDMI-1").unwrap().gamma.r, 10);
+
+    h.press_all(&[Key::Char('p'), Key::Char('1')]);
+    assert_eq!(h.mock.output("eDP-1").unwrap().brightness, 80);
+    assert_eq!(h.mock.output("HDMI-1").unwrap().gamma.r, 90);
+}
+
+#[test]
+fn state_is_saved_on_shutdown_when_changed() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("tuib").join("state.toml");
+    let mut h = Harness::two_outputs();
+    h.handler.set_state_path(Some(path.clone()));
+
+    h.press(Key::Char('4'));
+    h.app.lock().dispatch(IoEvent::Shutdown);
+    h.drain();
+    let state = State::load(&path).unwrap();
+    assert_eq!(state.displays["eDP-1"].brightness, 40);
+    assert_eq!(state.displays["HDMI-1"].gamma, [90, 80, 70]);
+
+    //an unchanged state isn't written again
+    std::fs::remove_file(&path).unwrap();
+    h.handler.save_state().unwrap();
+    assert!(!path.exists());
+    h.press(Key::Char('9'));
+    h.handler.save_state().unwrap();
+    assert_eq!(State::load(&path).unwrap().displays["eDP-1"].brightness, 90);
+
+    //a state edited out of range isn't restored
+    let edited = std::fs::read_to_string(&path).unwrap().replace("temperature = 6500", "temperature = 0");
+    std::fs::write(&path, edited).unwrap();
+    assert!(matches!(State::load(&path), Err(DispError::Profile(msg)) if msg.contains("temperature 0K")));
+}
+
+#[test]
+fn undo_and_redo_changes() {
+    let mut h = Harness::two_outputs();
+    h.press(Key::Char('1'));
+    assert_eq!(h.mock.output("eDP-1").unwrap().brightness, 10);
+    h.press(Key::Char('u'));
+    assert_eq!(h.mock.output("eDP-1").unwrap().brightness, 80);
+    h.press(Key::Ctrl('r'));
+    assert_eq!(h.mock.output("eDP-1").unwrap().brightness, 10);
+
+    //a temperature comes back with the exact gamma it replaced, and linked changes undo together
+    h.press_all(&[Key::Char('L'), Key::Char('t'), Key::Enter]);
+    assert_ne!(h.mock.output("HDMI-1").unwrap().gamma.r, 90);
+    h.press_all(&[Key::Char('S'), Key::Char('5')]);
+    assert_eq!(h.mock.output("eDP-1").unwrap().brightness, 50);
+    h.press_all(&[Key::Char('u'), Key::Char('u')]);
+    let hdmi = h.mock.output("HDMI-1").unwrap();
+    assert_eq!((hdmi.brightness, hdmi.gamma.r, hdmi.gamma.g, hdmi.gamma.b), (100, 90, 80, 70));
+    assert_eq!(h.mock.output("eDP-1").unwrap().brightness, 10);
+
+    h.press(Key::Char('U'));
+    let screen = h.screen();
+    assert!(screen.contains("HDMI-1 Temperature"), "{}", screen);
+    assert!(screen.contains("> eDP-1 Brightness 80 -> 10"), "{}", screen);
+    //undo still works with the history open, anything else doesn't get through
+    h.press_all(&[Key::Char('u'), Key::Char('9')]);
+    assert_eq!(h.mock.output("eDP-1").unwrap().brightness, 80);
+    h.press_all(&[Key::Esc, Key::Char('9')]);
+    assert_eq!(h.app.lock().history().done().len(), 1);
+    assert!(h.app.lock().history().undone().is_empty());
+}
+
+#[test]
+fn key_bindings_from_config_drive_actions_and_help() {
+    let config = Config::parse("[keys]\n\"<Ctrl+l>\" = \"TabRight\"\n\"<Ctrl+q>\" = \"Quit\"").unwrap();
+    let mut h = Harness::with_config(Mock::new().with_output("DP-1", 100, NEUTRAL), &config);
+    assert_eq!(h.press(Key::Char('q')), AppReturn::Continue);
+    h.press(Key::Char('L'));
+    assert_eq!(h.app.lock().state.tab_index(), Some(0));
+    h.press(Key::Ctrl('l'));
+    assert_eq!(h.app.lock().state.tab(), Some(Tab::Debug));
+
+    let screen = h.screen();
+    assert!(screen.contains("<Ctrl+l>"));
+    assert!(screen.contains("<Ctrl+q>"));
+    assert!(!screen.contains("<L>"));
+    assert_eq!(h.press(Key::Ctrl('q')), AppReturn::Exit);
+}
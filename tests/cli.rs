@@ -0,0 +1,159 @@
+mod common;
+
+use clap::Parser;
+use common::NEUTRAL;
+use tempfile::TempDir;
+use tuib::cli::{Cli, Command, Displays, Status};
+use tuib::disp_mgr::backend::Mock;
+use tuib::disp_mgr::backlight::Backlight;
+use tuib::disp_mgr::ddc::Ddc;
+use tuib::disp_mgr::error::{DispError, Result};
+use tuib::disp_mgr::DispMgr;
+
+///Parse args and run the command against mgr, the way main does
+fn run(mgr: &mut DispMgr<Mock>, args: &[&str]) -> Result<()> {
+    let cli = Cli::try_parse_from(std::iter::once("tuib").chain(args.iter().copied())).unwrap();
+    match cli.command {
+        Some(Command::Set(set)) => set.run(mgr),
+        Some(Command::Inc(step)) => step.run(mgr, true),
+        Some(Command::Dec(step)) => step.run(mgr, false),
+        Some(Command::Scale(scale)) => scale.run(mgr),
+        command => panic!("not a display command: {:?}", command),
+    }
+}
+
+fn mgr(dir: &TempDir) -> (Mock, DispMgr<Mock>) {
+    let mock = Mock::new().with_output("eDP-1", 80, NEUTRAL).with_output("HDMI-1", 100, NEUTRAL);
+    let dm = DispMgr::with_hardware(mock.clone(), Backlight::new(dir.path()), Ddc::new(dir.path(), dir.path())).unwrap();
+    (mock, dm)
+}
+
+#[test]
+fn set_inc_dec_and_scale() {
+    let dir = TempDir::new().unwrap();
+    let (mock, mut dm) = mgr(&dir);
+
+    run(&mut dm, &["set", "--output", "eDP-1", "--brightness", "70", "--gamma", "100:90:80"]).unwrap();
+    let edp = mock.output("eDP-1").unwrap();
+    assert_eq!((edp.brightness, edp.gamma.g, edp.gamma.b), (70, 90, 80));
+    assert_eq!(mock.output("HDMI-1").unwrap().brightness, 100);
+
+    //no output means every display
+    run(&mut dm, &["dec", "--step", "20"]).unwrap();
+    assert_eq!(mock.output("eDP-1").unwrap().brightness, 50);
+    assert_eq!(mock.output("HDMI-1").unwrap().brightness, 80);
+    run(&mut dm, &["inc", "-o", "HDMI-1"]).unwrap();
+    assert_eq!(mock.output("HDMI-1").unwrap().brightness, 85);
+
+    run(&mut dm, &["dec", "-o", "HDMI-1", "--prop", "temperature"]).unwrap();
+    assert_eq!(dm.get_disp_by_index(1).unwrap().temperature, 6000);
+    run(&mut dm, &["scale", "3", "-o", "HDMI-1"]).unwrap();
+    run(&mut dm, &["scale", "0", "-o", "eDP-1", "-p", "red"]).unwrap();
+    assert_eq!(mock.output("HDMI-1").unwrap().brightness, 30);
+    assert_eq!(mock.output("eDP-1").unwrap().gamma.r, 100);
+}
+
+#[test]
+fn only_luminance_needs_ddc() {
+    let needs_ddc = |args: &[&str]| {
+        let cli = Cli::try_parse_from(std::iter::once("tuib").chain(args.iter().copied())).unwrap();
+        cli.command.unwrap().needs_ddc()
+    };
+    assert!(!needs_ddc(&["set", "-b", "50", "--backlight", "20"]));
+    assert!(!needs_ddc(&["inc", "-p", "temperature"]));
+    assert!(!needs_ddc(&["scale", "3"]));
+    assert!(needs_ddc(&["set", "--luminance", "50"]));
+    assert!(needs_ddc(&["dec", "-p", "luminance"]));
+    assert!(needs_ddc(&["get"]));
+}
+
+#[test]
+fn failures_are_errors() {
+    let dir = TempDir::new().unwrap();
+    let (mock, mut dm) = mgr(&dir);
+    assert!(matches!(run(&mut dm, &["set", "-o", "DP-9", "-b", "10"]), Err(DispError::NoSuchOutput(_))));
+
+    //a display without a backlight fails, the others are still set
+    let result = run(&mut dm, &["set", "--backlight", "50", "--brightness", "40"]);
+    assert!(matches!(result, Err(DispError::Unsupported(_))));
+    assert_eq!(mock.output("HDMI-1").unwrap().brightness, 40);
+    assert_eq!(mock.output("eDP-1").unwrap().brightness, 40);
+
+    for args in [
+        &["set", "--gamma", "100:90"][..],
+        &["set", "--gamma", "0:90:80"],
+        &["set", "--brightness", "101"],
+        &["set", "--temperature", "500"],
+        &["scale", "10"],
+        &["inc", "--prop", "contrast"],
+    ] {
+        let err = Cli::try_parse_from(std::iter::once("tuib").chain(args.iter().copied())).unwrap_err();
+        assert_eq!(err.exit_code(), 2, "{:?}", args);
+    }
+}
+
+#[test]
+fn list_and_get() {
+    let dir = TempDir::new().unwrap();
+    let (_mock, mut dm) = mgr(&dir);
+    run(&mut dm, &["set", "-o", "HDMI-1", "--gamma", "90:80:70", "-b", "60"]).unwrap();
+    let parse = |args: &[&str]| Cli::try_parse_from(std::iter::once("tuib").chain(args.iter().copied())).unwrap();
+
+    let table = match parse(&["list"]).command {
+        Some(Command::List(list)) => list.run(&mut dm).unwrap(),
+        _ => unreachable!(),
+    };
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("OUTPUT  BACKEND  BRIGHTNESS  GAMMA"), "{}", table);
+    assert!(lines[2].starts_with("HDMI-1  mock     60%         90:80:70"), "{}", table);
+
+    let json = match parse(&["list", "--json"]).command {
+        Some(Command::List(list)) => list.run(&mut dm).unwrap(),
+        _ => unreachable!(),
+    };
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(json[1]["output"], "HDMI-1");
+    assert_eq!(json[1]["gamma"]["g"], 80);
+    assert_eq!(json[0]["backlight"], serde_json::Value::Null);
+
+    let mut get = |args: &[&str]| match parse(args).command {
+        Some(Command::Get(get)) => get.run(&mut dm),
+        _ => unreachable!(),
+    };
+    assert_eq!(get(&["get", "-p", "brightness"]).unwrap(), "80\n60");
+    assert_eq!(get(&["get", "-o", "HDMI-1", "-p", "temperature"]).unwrap(), "6500");
+    assert_eq!(get(&["get", "-p", "green", "--json"]).unwrap(), r#"{"HDMI-1":80,"eDP-1":100}"#);
+    assert_eq!(get(&["get", "-o", "eDP-1", "--json"]).unwrap().matches("\"output\"").count(), 1);
+    assert!(matches!(get(&["get", "-p", "backlight"]), Err(DispError::Unsupported(_))));
+}
+
+#[test]
+fn status_lines_and_actions() {
+    let dir = TempDir::new().unwrap();
+    let (mock, mut dm) = mgr(&dir);
+    let status = |args: &[&str]| match Cli::try_parse_from(["tuib", "status"].iter().chain(args)).unwrap().command {
+        Some(Command::Status(status)) => status,
+        _ => unreachable!(),
+    };
+    let reports = dm.reports().unwrap();
+
+    assert_eq!(status(&[]).render(&reports).unwrap(), "eDP-1: 80%  HDMI-1: 100%");
+    let line = status(&["-o", "HDMI-1", "-f", "{output} {temp}K {backlight}"]).render(&reports).unwrap();
+    assert_eq!(line, "HDMI-1 6500K -");
+    let waybar: serde_json::Value = serde_json::from_str(&status(&["--waybar"]).render(&reports).unwrap()).unwrap();
+    assert_eq!(waybar["text"], "eDP-1: 80%  HDMI-1: 100%");
+    assert_eq!(waybar["tooltip"], "eDP-1: 80% 6500K\nHDMI-1: 100% 6500K");
+    assert_eq!(waybar["percentage"], 80);
+    let err = Cli::try_parse_from(["tuib", "status", "-f", "{output} {contrast}"]).unwrap_err();
+    assert_eq!(err.exit_code(), 2);
+
+    //scrolling moves the shown displays by --step
+    assert_eq!(Status::parse_action("4"), Some(true));
+    assert_eq!(Status::parse_action(r#"{"name":"tuib","button":5,"x":10}"#), Some(false));
+    assert_eq!(Status::parse_action("1"), None);
+    let scroll = status(&["-o", "eDP-1", "--step", "10"]);
+    scroll.step.run(&mut dm, Status::parse_action("down").unwrap()).unwrap();
+    assert_eq!(mock.output("eDP-1").unwrap().brightness, 70);
+    assert_eq!(mock.output("HDMI-1").unwrap().brightness, 100);
+}
@@ -0,0 +1,230 @@
+#![allow(dead_code)]
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use parking_lot::FairMutex;
+use tempfile::TempDir;
+use tui::backend::TestBackend;
+use tui::buffer::Buffer;
+use tui::Terminal;
+use tuib::app::{ui, App, AppReturn};
+use tuib::config::Config;
+use tuib::disp_mgr::backend::Mock;
+use tuib::disp_mgr::backlight::Backlight;
+use tuib::disp_mgr::ddc::Ddc;
+use tuib::disp_mgr::disp::Gamma;
+use tuib::disp_mgr::DispMgr;
+use tuib::inputs::key::Key;
+use tuib::io::handler::IoHandler;
+use tuib::io::hotplug;
+use tuib::io::IoEvent;
+
+pub const NEUTRAL: Gamma = Gamma { r: 100, g: 100, b: 100 };
+
+///Runs App and IoHandler on one thread against a Mock backend. Every IoEvent the App dispatches
+///is handled before press returns, so state can be checked right away.
+pub struct Harness {
+    pub app: Arc<FairMutex<App>>,
+    pub handler: IoHandler<Mock>,
+    pub mock: Mock,
+    rx: Receiver<IoEvent>,
+    //keeps the empty sysfs and dev trees alive
+    _hw: TempDir,
+}
+
+impl Harness {
+    ///Build an initialized App for the outputs of mock, with no backlight or DDC/CI hardware
+    pub fn new(mock: Mock) -> Self {
+        Harness::with_config(mock, &Config::default())
+    }
+
+    ///Like new, with an App built from config
+    pub fn with_config(mock: Mock, config: &Config) -> Self {
+        let hw = TempDir::new().unwrap();
+        let disp_mgr = DispMgr::with_hardware(
+            mock.clone(),
+            Backlight::new(hw.path().join("backlight")),
+            Ddc::new(hw.path().join("drm"), hw.path().join("dev")),
+        )
+        .unwrap();
+        Harness::with_disp_mgr(mock, disp_mgr, hw, config)
+    }
+
+    pub fn with_disp_mgr(mock: Mock, disp_mgr: DispMgr<Mock>, hw: TempDir, config: &Config) -> Self {
+        let (tx, rx) = channel();
+        hotplug::start(&mock, tx.clone());
+        let app = Arc::new(FairMutex::new(App::with_config(tx, config)));
+        let mut handler = IoHandler::new(Arc::clone(&app), disp_mgr);
+        handler.set_groups(config.groups.clone());
+        let mut harness = Harness { app, handler, mock, rx, _hw: hw };
+        harness.app.lock().dispatch(IoEvent::Initialize);
+        harness.drain();
+        harness
+    }
+
+    ///Two outputs, a laptop panel and an external monitor
+    pub fn two_outputs() -> Self {
+        Harness::new(
+            Mock::new()
+                .with_output("eDP-1", 80, NEUTRAL)
+                .with_output("HDMI-1", 100, Gamma { r: 90, g: 80, b: 70 }),
+        )
+    }
+
+    ///Feed a key through App::do_action and handle whatever it dispatched
+    pub fn press(&mut self, key: Key) -> AppReturn {
+        let ret = self.app.lock().do_action(key);
+        self.drain();
+        ret
+    }
+
+    pub fn press_all(&mut self, keys: &[Key]) {
+        for key in keys {
+            self.press(*key);
+        }
+    }
+
+    ///Handle every pending IoEvent
+    pub fn drain(&mut self) {
+        while let Ok(event) = self.rx.try_recv() {
+            self.handler.handle_io_event(event);
+        }
+    }
+
+    ///Draw the ui into a TestBackend
+    pub fn draw(&self, width: u16, height: u16) -> Buffer {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        let app = self.app.lock();
+        terminal.draw(|f| ui::draw(f, &app)).unwrap();
+        terminal.backend().buffer().clone()
+    }
+
+    ///Draw the ui and return it as text, one line per row
+    pub fn screen(&self) -> String {
+        buffer_text(&self.draw(100, 40))
+    }
+}
+
+///An IO thread like `tuib daemon` runs, for eDP-1 at 80 and HDMI-1 at 100. Requests go in as
+///IoEvent::Ipc on the sender. The thread ends on shutdown
+pub fn daemon(dir: &TempDir) -> (Mock, Sender<IoEvent>, JoinHandle<()>) {
+    let mock = Mock::new().with_output("eDP-1", 80, NEUTRAL).with_output("HDMI-1", 100, NEUTRAL);
+    let disp_mgr = DispMgr::with_hardware(
+        mock.clone(),
+        Backlight::new(dir.path().join("backlight")),
+        Ddc::new(dir.path().join("drm"), dir.path().join("dev")),
+    )
+    .unwrap();
+    let (tx, rx) = channel();
+    let app = Arc::new(FairMutex::new(App::with_config(tx.clone(), &Config::default())));
+    app.lock().dispatch(IoEvent::Initialize);
+    let mut handler = IoHandler::new(app, disp_mgr);
+    handler.set_state_path(Some(dir.path().join("state.toml")));
+    let thread = thread::spawn(move || handler.run(rx));
+    (mock, tx, thread)
+}
+
+pub fn buffer_text(buffer: &Buffer) -> String {
+    let width = buffer.area.width as usize;
+    buffer
+        .content
+        .chunks(width)
+        .map(|row| row.iter().map(|c| c.symbol.as_str()).collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+///In-process DDC/CI monitor on a fake i2c bus. Answers get/set vcp and capabilities requests the
+///way a real monitor would. Clones share state
+#[derive(Debug, Clone, Default)]
+pub struct FakeMonitor {
+    state: Arc<parking_lot::Mutex<FakeMonitorState>>,
+}
+
+#[derive(Debug, Default)]
+struct FakeMonitorState {
+    caps: String,
+    vcp: std::collections::HashMap<u8, (u16, u16)>,
+    reply: Vec<u8>,
+}
+
+impl FakeMonitor {
+    pub fn new(caps: &str) -> Self {
+        let monitor = FakeMonitor::default();
+        monitor.state.lock().caps = caps.to_string();
+        monitor
+    }
+
+    ///Support a vcp code with a current and max value
+    pub fn with_vcp(self, code: u8, cur: u16, max: u16) -> Self {
+        self.state.lock().vcp.insert(code, (cur, max));
+        self
+    }
+
+    pub fn vcp(&self, code: u8) -> Option<u16> {
+        self.state.lock().vcp.get(&code).map(|v| v.0)
+    }
+
+    ///A DdcCi talking to this monitor, without the delays real monitors need
+    pub fn ddc(&self) -> tuib::disp_mgr::ddc::Monitor {
+        tuib::disp_mgr::ddc::DdcCi::new(Box::new(self.clone()) as Box<dyn tuib::disp_mgr::ddc::I2c + Send>)
+            .with_delay(std::time::Duration::ZERO)
+    }
+}
+
+impl tuib::disp_mgr::ddc::I2c for FakeMonitor {
+    fn write(&mut self, addr: u16, data: &[u8]) -> std::io::Result<()> {
+        use tuib::disp_mgr::ddc::protocol::checksum;
+        assert_eq!(addr, 0x37);
+        let (msg, chk) = data.split_at(data.len() - 1);
+        assert_eq!(checksum(0x6E, msg), chk[0], "bad checksum from host");
+        assert_eq!(msg[0], 0x51);
+        assert_eq!((msg[1] & 0x7f) as usize, msg.len() - 2);
+
+        let mut state = self.state.lock();
+        let payload = match msg[2] {
+            //get vcp
+            0x01 => match state.vcp.get(&msg[3]) {
+                Some((cur, max)) => {
+                    let [mh, ml] = max.to_be_bytes();
+                    let [ch, cl] = cur.to_be_bytes();
+                    vec![0x02, 0x00, msg[3], 0x00, mh, ml, ch, cl]
+                }
+                None => vec![0x02, 0x01, msg[3], 0x00, 0, 0, 0, 0],
+            },
+            //set vcp
+            0x03 => {
+                let val = u16::from_be_bytes([msg[4], msg[5]]);
+                if let Some(v) = state.vcp.get_mut(&msg[3]) {
+                    v.0 = val;
+                }
+                state.reply.clear();
+                return Ok(());
+            }
+            //capabilities
+            0xF3 => {
+                let offset = u16::from_be_bytes([msg[3], msg[4]]) as usize;
+                let caps = state.caps.as_bytes();
+                let end = (offset + 32).min(caps.len());
+                let mut payload = vec![0xE3, msg[3], msg[4]];
+                payload.extend_from_slice(&caps[offset.min(end)..end]);
+                payload
+            }
+            op => panic!("fake monitor got unknown opcode 0x{:02x}", op),
+        };
+        let mut reply = vec![0x6E, 0x80 | payload.len() as u8];
+        reply.extend(payload);
+        reply.push(checksum(0x50, &reply));
+        state.reply = reply;
+        Ok(())
+    }
+
+    fn read(&mut self, addr: u16, buf: &mut [u8]) -> std::io::Result<()> {
+        assert_eq!(addr, 0x37);
+        let state = self.state.lock();
+        buf.fill(0);
+        let len = state.reply.len().min(buf.len());
+        buf[..len].copy_from_slice(&state.reply[..len]);
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+use std::fs;
+use tempfile::TempDir;
+use tuib::app::actions::Action;
+use tuib::config::{Config, ConfigError, Preset};
+use tuib::inputs::key::Key;
+
+#[test]
+fn presets_from_config_extend_the_defaults() {
+    let config = Config::parse(
+        r#"
+        [[presets]]
+        name = "Candle"
+        kelvin = 1500
+
+        [[presets]]
+        name = "Sodium lamp"
+        kelvin = 2100
+        "#,
+    )
+    .unwrap();
+    let presets = config.presets();
+    assert_eq!(presets[0], Preset::new("Candle", 1500));
+    assert_eq!(presets.last(), Some(&Preset::new("Sodium lamp", 2100)));
+    assert_eq!(presets.len(), Config::default().presets().len() + 1);
+}
+
+#[test]
+fn missing_config_is_default_and_bad_config_is_an_error() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("config.toml");
+    assert!(Config::load_from(&path).unwrap().presets.is_empty());
+
+    fs::write(&path, "presets = 3").unwrap();
+    let err = Config::load_from(&path).unwrap_err();
+    assert!(matches!(err, ConfigError::Parse(..)));
+    assert!(err.to_string().contains("config.toml"));
+}
+
+#[test]
+fn keys_parse_the_way_they_print() {
+    for key in [Key::Ctrl('l'), Key::Alt(' '), Key::Char('<'), Key::Char('q'), Key::Enter, Key::Left] {
+        assert_eq!(key.to_string().parse::<Key>(), Ok(key));
+    }
+    assert_eq!("ctrl+L".parse::<Key>(), Ok(Key::Ctrl('L')));
+    assert!("<Hyper+x>".parse::<Key>().is_err());
+}
+
+#[test]
+fn key_bindings_replace_defaults_per_action() {
+    let config = Config::parse(
+        r#"
+        [keys]
+        "<Ctrl+l>" = "TabRight"
+        "<Ctrl+h>" = "tableft"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.keys.keys(Action::TabRight), [Key::Ctrl('l')]);
+    assert_eq!(config.keys.keys(Action::TabLeft), [Key::Ctrl('h')]);
+    assert_eq!(config.keys.keys(Action::Quit), [Key::Char('q')]);
+}
+
+#[test]
+fn conflicting_or_unknown_bindings_are_errors() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("config.toml");
+    fs::write(&path, "[keys]\n\"<l>\" = \"Quit\"\n").unwrap();
+    let err = Config::load_from(&path).unwrap_err().to_string();
+    assert!(err.contains("<l> is bound to Quit, MoveRight"), "{}", err);
+
+    assert!(Config::parse("[keys]\n\"<x>\" = \"Explode\"").unwrap_err().to_string().contains("unknown action 'Explode'"));
+    assert!(Config::parse("[keys]\n\"<Hyper+x>\" = \"Quit\"").is_err());
+}
@@ -0,0 +1,99 @@
+#![cfg(feature = "dbus")]
+mod common;
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use tempfile::TempDir;
+use tuib::ipc::dbus::{self, Values};
+use tuib::ipc::{Mode, Request};
+use tuib::io::IoEvent;
+use zbus::blocking::connection::Builder;
+use zbus::blocking::{Connection, Proxy};
+
+///A private bus, so tests don't need or touch the session bus. Killed on drop
+struct Bus {
+    daemon: Child,
+    address: String,
+}
+
+impl Bus {
+    fn start(dir: &TempDir) -> Self {
+        let config = dir.path().join("bus.conf");
+        std::fs::write(
+            &config,
+            format!(
+                r#"<busconfig>
+  <type>session</type>
+  <listen>unix:dir={}</listen>
+  <policy context="default">
+    <allow send_destination="*" eavesdrop="true"/>
+    <allow eavesdrop="true"/>
+    <allow own="*"/>
+  </policy>
+</busconfig>"#,
+                dir.path().display()
+            ),
+        )
+        .unwrap();
+        let mut daemon = Command::new("dbus-daemon")
+            .arg(format!("--config-file={}", config.display()))
+            .args(["--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("the dbus tests need dbus-daemon");
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap()).read_line(&mut address).unwrap();
+        Bus { daemon, address: address.trim().to_string() }
+    }
+
+    fn connect(&self) -> Connection {
+        Builder::address(self.address.as_str()).unwrap().build().unwrap()
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+#[test]
+fn methods_and_changed_signal() {
+    let dir = TempDir::new().unwrap();
+    let bus = Bus::start(&dir);
+    let (mock, tx, thread) = common::daemon(&dir);
+    let _service = dbus::serve(Builder::address(bus.address.as_str()).unwrap(), tx.clone()).unwrap();
+
+    let connection = bus.connect();
+    let proxy = Proxy::new(&connection, dbus::NAME, dbus::PATH, dbus::NAME).unwrap();
+    let mut changed = proxy.receive_signal("Changed").unwrap();
+
+    let outputs: Vec<String> = proxy.call("List", &()).unwrap();
+    assert_eq!(outputs, ["eDP-1", "HDMI-1"]);
+    let brightness: u32 = proxy.call("Get", &("HDMI-1", "brightness")).unwrap();
+    assert_eq!(brightness, 100);
+
+    proxy.call::<_, _, ()>("Set", &("eDP-1", "brightness", 40u32)).unwrap();
+    assert_eq!(mock.output("eDP-1").unwrap().brightness, 40);
+    let displays: HashMap<String, Values> = changed.next().unwrap().body().deserialize().unwrap();
+    assert_eq!(displays["eDP-1"]["brightness"], 40);
+    assert_eq!(displays["HDMI-1"]["temperature"], 6500);
+
+    proxy.call::<_, _, ()>("Increment", &("eDP-1", "brightness", -10i32)).unwrap();
+    assert_eq!(mock.output("eDP-1").unwrap().brightness, 30);
+    proxy.call::<_, _, ()>("Set", &("HDMI-1", "temperature", 4000u32)).unwrap();
+    let kelvin: u32 = proxy.call("Get", &("HDMI-1", "temperature")).unwrap();
+    assert_eq!(kelvin, 4000);
+
+    //failures come back as D-Bus errors
+    assert!(proxy.call::<_, _, u32>("Get", &("DP-9", "brightness")).is_err());
+    assert!(proxy.call::<_, _, u32>("Get", &("eDP-1", "backlight")).is_err());
+    assert!(proxy.call::<_, _, ()>("Set", &("eDP-1", "contrast", 10u32)).is_err());
+    assert!(proxy.call::<_, _, ()>("Set", &("eDP-1", "brightness", 101u32)).is_err());
+
+    let (reply_tx, _reply_rx) = std::sync::mpsc::channel();
+    tx.send(IoEvent::Ipc(Request::Shutdown, Mode::default(), reply_tx)).unwrap();
+    thread.join().unwrap();
+}
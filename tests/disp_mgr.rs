@@ -0,0 +1,314 @@
+mod common;
+
+use std::fs;
+use std::os::unix::fs::symlink;
+use common::{FakeMonitor, NEUTRAL};
+use tempfile::TempDir;
+use tuib::disp_mgr::backend::ramp::{estimate, ramp};
+use tuib::disp_mgr::backend::xrandr::parse_verbose;
+use tuib::disp_mgr::backend::Mock;
+use tuib::disp_mgr::backlight::Backlight;
+use tuib::disp_mgr::ddc::caps::parse_vcp;
+use tuib::disp_mgr::ddc::{Ddc, VcpFeature};
+use tuib::disp_mgr::profile::{Profiles, State};
+use tuib::disp_mgr::temperature;
+use tuib::disp_mgr::disp::{DispProp, Gamma};
+use tuib::disp_mgr::error::DispError;
+use tuib::disp_mgr::history;
+use tuib::disp_mgr::DispMgr;
+
+const CAPS: &str = "(prot(monitor)type(lcd)model(FAKE)cmds(01 02 03 0C F3)\
+                    vcp(02 04 10 12 14(05 08 0B) 16 18 1A 60(0F 11 12) DF)mccs_ver(2.1))";
+
+///Fake /sys/class/backlight with one device linked to a connector and one firmware device
+fn fake_backlight(dir: &TempDir) -> Backlight {
+    let root = dir.path().join("backlight");
+    let card = dir.path().join("drm").join("card0-eDP-1");
+    fs::create_dir_all(&card).unwrap();
+    for (name, max, cur, link) in [("intel_backlight", 1000, 250, true), ("acpi_video0", 10, 10, false)] {
+        let dev = root.join(name);
+        fs::create_dir_all(&dev).unwrap();
+        fs::write(dev.join("max_brightness"), format!("{}\n", max)).unwrap();
+        fs::write(dev.join("brightness"), format!("{}\n", cur)).unwrap();
+        fs::write(dev.join("type"), if link { "raw" } else { "firmware" }).unwrap();
+        if link {
+            symlink(&card, dev.join("device")).unwrap();
+        }
+    }
+    Backlight::new(root)
+}
+
+fn mgr(mock: Mock, backlight: Backlight, ddc: Ddc) -> DispMgr<Mock> {
+    DispMgr::with_hardware(mock, backlight, ddc).unwrap()
+}
+
+#[test]
+fn gamma_string_is_inverse_of_xrandr_exponent() {
+    let g = Gamma::from_gamma_string("1.3:1.7:2.5").unwrap();
+    assert_eq!((g.r, g.g, g.b), (77, 59, 40));
+    assert_eq!(Gamma { r: 80, g: 60, b: 40 }.to_gamma_string(), "0.8:0.6:0.4");
+    assert!(Gamma::from_gamma_string("1.0:1.0").is_err());
+    assert!(Gamma::from_gamma_string("a:b:c").is_err());
+}
+
+#[test]
+fn parse_xrandr_verbose() {
+    let verbose = "Screen 0: minimum 8 x 8, current 1920 x 1080\n\
+                   eDP-1 connected primary 1920x1080+0+0 (0x45) normal\n\
+                   \tIdentifier: 0x42\n\
+                   \tGamma:      1.0:1.25:2.5\n\
+                   \tBrightness: 0.70\n\
+                   HDMI-1 disconnected (normal left inverted right x axis y axis)\n\
+                   \tGamma:      1.0:1.0:1.0\n";
+    let (brightness, gamma) = parse_verbose(verbose, "eDP-1").unwrap();
+    assert_eq!(brightness, 70);
+    assert_eq!((gamma.r, gamma.g, gamma.b), (100, 80, 40));
+    //HDMI-1 has no brightness line
+    assert!(parse_verbose(verbose, "HDMI-1").is_err());
+    assert!(parse_verbose(verbose, "DP-3").is_err());
+}
+
+#[test]
+fn ramp_round_trips() {
+    for (brightness, r, g, b) in [(100, 100, 100, 100), (80, 80, 60, 40), (35, 100, 50, 25)] {
+        let (got_brightness, got) = estimate(&ramp(256, r, brightness), &ramp(256, g, brightness), &ramp(256, b, brightness));
+        assert_eq!(got_brightness, brightness);
+        assert_eq!((got.r, got.g, got.b), (r, g, b));
+    }
+}
+
+#[test]
+fn backlight_matches_connector_and_writes_raw_value() {
+    let dir = TempDir::new().unwrap();
+    let mock = Mock::new().with_output("eDP-1", 100, NEUTRAL).with_output("HDMI-1", 100, NEUTRAL);
+    let mut dm = mgr(mock.clone(), fake_backlight(&dir), Ddc::new(dir.path().join("drm"), dir.path()));
+
+    assert_eq!(dm.disps[0].backlight, Some(25));
+    assert_eq!(dm.disps[1].backlight, None);
+    assert!(dm.disps[0].props().contains(&DispProp::Backlight));
+    assert!(!dm.disps[1].props().contains(&DispProp::Backlight));
+
+    dm.scale_value_by_index(0, DispProp::Backlight, 6).unwrap();
+    let raw = fs::read_to_string(dir.path().join("backlight/intel_backlight/brightness")).unwrap();
+    assert_eq!(raw, "600");
+    //backlight changes don't touch gamma
+    assert!(mock.applied().is_empty());
+}
+
+#[test]
+fn unreadable_backlight_is_left_out() {
+    let dir = TempDir::new().unwrap();
+    let backlight = fake_backlight(&dir);
+    fs::write(dir.path().join("backlight/intel_backlight/max_brightness"), "0\n").unwrap();
+    let mock = Mock::new().with_output("eDP-1", 100, NEUTRAL).with_output("HDMI-1", 100, NEUTRAL);
+    let dm = mgr(mock, backlight, Ddc::new(dir.path().join("drm"), dir.path()));
+    assert_eq!(dm.get_name_list(), ["eDP-1", "HDMI-1"]);
+    assert_eq!(dm.disps[0].backlight, None);
+}
+
+#[test]
+fn unlinked_backlight_goes_to_internal_panel() {
+    let dir = TempDir::new().unwrap();
+    let mut backlight = fake_backlight(&dir);
+    //intel_backlight points at eDP-1, which xrandr calls LVDS1 here, so it can't be matched
+    backlight.scan(&["LVDS1".to_string()]);
+    assert_eq!(backlight.read("LVDS1").unwrap(), Some(100));
+}
+
+#[test]
+fn ddc_luminance_and_features() {
+    let monitor = FakeMonitor::new(CAPS)
+        .with_vcp(0x10, 40, 100)
+        .with_vcp(0x12, 50, 100)
+        .with_vcp(0x14, 0x05, 0x0B)
+        .with_vcp(0x16, 30, 60)
+        .with_vcp(0x60, 0x0F, 0x12);
+    let dir = TempDir::new().unwrap();
+    let mut ddc = Ddc::new(dir.path(), dir.path());
+    ddc.insert("DP-1".to_string(), monitor.ddc()).unwrap();
+
+    let mock = Mock::new().with_output("eDP-1", 100, NEUTRAL).with_output("DP-1", 100, NEUTRAL);
+    let mut dm = mgr(mock, Backlight::new(dir.path()), ddc);
+    let disp = dm.get_disp_by_index(1).unwrap();
+    assert_eq!(disp.luminance, Some(40));
+    assert_eq!(dm.disps[0].luminance, None);
+
+    //only features the monitor both lists and answers for, in a fixed order
+    let codes: Vec<u8> = disp.vcp.iter().map(|f| f.code).collect();
+    assert_eq!(codes, vec![0x12, 0x16, 0x14, 0x60]);
+    assert_eq!(disp.vcp_feature(0x16).unwrap().pct(), 50);
+    assert_eq!(disp.vcp_feature(0x60).unwrap().value_name(), "DisplayPort-1");
+
+    dm.increment_value_by_index(1, DispProp::Luminance).unwrap();
+    assert_eq!(monitor.vcp(0x10), Some(41));
+    dm.scale_value_by_index(1, DispProp::Vcp(0x12), 3).unwrap();
+    assert_eq!(monitor.vcp(0x12), Some(30));
+
+    //pick-lists cycle through what the capabilities string allows
+    dm.increment_value_by_index(1, DispProp::Vcp(0x60)).unwrap();
+    assert_eq!(monitor.vcp(0x60), Some(0x11));
+    dm.decrement_value_by_index(1, DispProp::Vcp(0x60)).unwrap();
+    dm.decrement_value_by_index(1, DispProp::Vcp(0x60)).unwrap();
+    assert_eq!(monitor.vcp(0x60), Some(0x12));
+
+    //history keeps raw values, a pick-list has no percentage to go back to
+    let before = history::values_of(&dm.disps);
+    dm.increment_value_by_index(1, DispProp::Vcp(0x60)).unwrap();
+    let entry = history::diff(&before, &history::values_of(&dm.disps));
+    assert_eq!(entry.changes.len(), 1);
+    assert_eq!((entry.changes[0].from, entry.changes[0].to), (0x12, 0x0F));
+    dm.undo(&entry).unwrap();
+    assert_eq!(monitor.vcp(0x60), Some(0x12));
+    dm.redo(&entry).unwrap();
+    assert_eq!(monitor.vcp(0x60), Some(0x0F));
+}
+
+#[test]
+fn vcp_step_stays_in_range() {
+    //values come from the monitor, a broken one can report anything
+    let mut feature = VcpFeature { code: 0x12, cur: 0xFFFF, max: 0xFFFF, choices: vec![] };
+    feature.step(true);
+    assert_eq!(feature.cur, 0xFFFF);
+    feature.cur = 0;
+    feature.step(false);
+    assert_eq!(feature.cur, 0);
+}
+
+#[test]
+fn ddc_rejects_monitor_without_luminance() {
+    let monitor = FakeMonitor::new(CAPS).with_vcp(0x12, 50, 100);
+    let dir = TempDir::new().unwrap();
+    let mut ddc = Ddc::new(dir.path(), dir.path());
+    assert!(ddc.insert("DP-1".to_string(), monitor.ddc()).is_err());
+}
+
+#[test]
+fn parse_capabilities() {
+    let codes = parse_vcp(CAPS);
+    assert_eq!(codes.len(), 10);
+    assert_eq!(codes[4], (0x14, vec![0x05, 0x08, 0x0B]));
+    assert_eq!(codes[8], (0x60, vec![0x0F, 0x11, 0x12]));
+    //some monitors leave out the spaces
+    assert_eq!(parse_vcp("vcp(1012(01 02)60)"), vec![(0x10, vec![]), (0x12, vec![1, 2]), (0x60, vec![])]);
+    assert!(parse_vcp("(prot(monitor))").is_empty());
+}
+
+#[test]
+fn bad_names_and_indexes_are_errors() {
+    let dir = TempDir::new().unwrap();
+    let mock = Mock::new().with_output("eDP-1", 100, NEUTRAL);
+    let mut dm = mgr(mock, Backlight::new(dir.path()), Ddc::new(dir.path(), dir.path()));
+    assert!(matches!(dm.get_disp_by_index(3), Err(DispError::NoSuchIndex(3))));
+    assert!(matches!(dm.increment_value_by_index(1, DispProp::R), Err(DispError::NoSuchIndex(1))));
+    assert!(matches!(
+        dm.set_value_by_name("DP-9".to_string(), DispProp::R, 50),
+        Err(DispError::NoSuchOutput(name)) if name == "DP-9"
+    ));
+    dm.set_value_by_name("eDP-1".to_string(), DispProp::R, 50).unwrap();
+    assert_eq!(dm.get_disp_by_name("eDP-1".to_string()).unwrap().gamma.r, 50);
+}
+
+#[test]
+fn temperature_table() {
+    let neutral = temperature::to_gamma(6500);
+    assert_eq!((neutral.r, neutral.g, neutral.b), (100, 100, 100));
+    let warm = temperature::to_gamma(2000);
+    assert_eq!(warm.r, 100);
+    assert!(warm.b < 10);
+    let cold = temperature::to_gamma(10000);
+    assert_eq!(cold.b, 100);
+    assert!(cold.r < 90);
+    //out of range clamps, and the gamma leads back to the temperature it came from
+    assert_eq!(temperature::to_gamma(500).b, temperature::to_gamma(1000).b);
+    for k in [1500, 3400, 6500, 8000] {
+        assert_eq!(temperature::from_gamma(temperature::to_gamma(k)), k);
+    }
+}
+
+#[test]
+fn profiles_roundtrip_and_match_by_name() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("tuib").join("profiles.toml");
+    let mock = Mock::new().with_output("eDP-1", 70, NEUTRAL).with_output("DP-1", 40, NEUTRAL);
+    let mut dm = mgr(mock.clone(), Backlight::new(dir.path()), Ddc::new(dir.path(), dir.path()));
+
+    let mut profiles = Profiles::default();
+    profiles.profiles.insert("movie".to_string(), dm.snapshot());
+    profiles.save(&path).unwrap();
+    let loaded = Profiles::load(&path).unwrap();
+    assert_eq!(loaded.get("movie").unwrap(), profiles.get("movie").unwrap());
+    assert!(matches!(loaded.get("photo"), Err(DispError::Profile(_))));
+
+    //a profile only touches the displays it knows
+    let mut movie = loaded.get("movie").unwrap().clone();
+    movie.remove("DP-1");
+    movie.insert("HDMI-9".to_string(), movie["eDP-1"].clone());
+    dm.set_value_by_index(0, DispProp::Brightness, 10).unwrap();
+    dm.set_value_by_index(1, DispProp::Brightness, 10).unwrap();
+    dm.apply_profile(&movie).unwrap();
+    assert_eq!(mock.output("eDP-1").unwrap().brightness, 70);
+    assert_eq!(mock.output("DP-1").unwrap().brightness, 10);
+}
+
+#[test]
+fn out_of_range_profiles_are_rejected_or_clamped() {
+    let dir = TempDir::new().unwrap();
+    let mock = Mock::new().with_output("eDP-1", 70, NEUTRAL);
+    let mut dm = mgr(mock.clone(), Backlight::new(dir.path()), Ddc::new(dir.path(), dir.path()));
+    let path = dir.path().join("tuib").join("profiles.toml");
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(
+        &path,
+        "[profiles.night.eDP-1]\nbrightness = 50\ngamma = [100, 100, 100]\ntemperature = 500\n",
+    )
+    .unwrap();
+    let err = Profiles::load(&path).unwrap_err();
+    assert!(matches!(&err, DispError::Profile(msg) if msg.contains("profile night") && msg.contains("eDP-1")));
+
+    //applied anyway, nothing out of range reaches the backend
+    let settings = toml::from_str("brightness = 250\ngamma = [0, 100, 180]\ntemperature = 500\n").unwrap();
+    dm.apply_settings(0, &settings).unwrap();
+    let out = mock.output("eDP-1").unwrap();
+    assert_eq!((out.brightness, out.gamma), (100, Gamma { r: 1, g: 100, b: 100 }));
+    let disp = dm.get_disp_by_index(0).unwrap();
+    assert_eq!((disp.temperature, disp.get_value(DispProp::Temperature)), (temperature::MIN, 0));
+}
+
+#[test]
+fn hardware_at_zero_round_trips_through_profiles() {
+    let dir = TempDir::new().unwrap();
+    let backlight = fake_backlight(&dir);
+    fs::write(dir.path().join("backlight").join("intel_backlight").join("brightness"), "0\n").unwrap();
+    let mut ddc = Ddc::new(dir.path().join("drm"), dir.path().join("dev"));
+    ddc.insert("DP-1".to_string(), FakeMonitor::new(CAPS).with_vcp(0x10, 0, 100).ddc()).unwrap();
+    let mock = Mock::new().with_output("eDP-1", 70, NEUTRAL).with_output("DP-1", 40, NEUTRAL);
+    let dm = mgr(mock, backlight, ddc);
+
+    //dimmed all the way is a value the hardware reports, so it has to load back
+    let snapshot = dm.snapshot();
+    assert_eq!((snapshot["eDP-1"].backlight, snapshot["DP-1"].luminance), (Some(0), Some(0)));
+    let path = dir.path().join("tuib").join("profiles.toml");
+    let mut profiles = Profiles::default();
+    profiles.profiles.insert("dark".to_string(), snapshot);
+    profiles.save(&path).unwrap();
+    assert_eq!(Profiles::load(&path).unwrap().get("dark").unwrap(), profiles.get("dark").unwrap());
+}
+
+#[test]
+fn state_with_backlight_at_zero_restores() {
+    let dir = TempDir::new().unwrap();
+    let backlight = fake_backlight(&dir);
+    let brightness = dir.path().join("backlight").join("intel_backlight").join("brightness");
+    fs::write(&brightness, "0\n").unwrap();
+    let mock = Mock::new().with_output("eDP-1", 70, NEUTRAL);
+    let mut dm = mgr(mock, backlight, Ddc::new(dir.path().join("drm"), dir.path().join("dev")));
+
+    //what the IO thread saves when the panel was dimmed all the way
+    let path = dir.path().join("tuib").join("state.toml");
+    State { displays: dm.snapshot() }.save(&path).unwrap();
+    dm.set_value_by_index(0, DispProp::Backlight, 50).unwrap();
+
+    //tuib restore. tuib never sets a backlight below 1%, so that's where it goes back to
+    dm.apply_profile(&State::load(&path).unwrap().displays).unwrap();
+    assert_eq!(fs::read_to_string(&brightness).unwrap().trim(), "10");
+}
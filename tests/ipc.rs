@@ -0,0 +1,147 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use parking_lot::FairMutex;
+use std::thread::JoinHandle;
+use clap::Parser;
+use tempfile::TempDir;
+use tuib::cli::{Cli, Command};
+use tuib::disp_mgr::backend::Mock;
+use tuib::disp_mgr::disp::DispProp;
+use tuib::disp_mgr::error::DispError;
+use tuib::app::App;
+use tuib::config::Config;
+use tuib::io::IoEvent;
+use tuib::ipc::client::{self, Client};
+use tuib::ipc::{server, Request, Response};
+
+///common::daemon, listening on a socket in dir
+fn daemon(dir: &TempDir) -> (Mock, PathBuf, JoinHandle<()>) {
+    let (mock, tx, thread) = common::daemon(dir);
+    let socket = dir.path().join("tuib.sock");
+    server::listen(&socket, tx).unwrap();
+    (mock, socket, thread)
+}
+
+#[test]
+fn requests_and_commands() {
+    let dir = TempDir::new().unwrap();
+    let (mock, socket, thread) = daemon(&dir);
+    let mut client = Client::connect(&socket).unwrap();
+
+    let set = Request::Set { output: String::from("eDP-1"), prop: DispProp::Brightness, value: 30, fade_ms: 0 };
+    client.call(&set).unwrap();
+    assert_eq!(mock.output("eDP-1").unwrap().brightness, 30);
+    let set = Request::Set { output: String::from("DP-9"), prop: DispProp::Brightness, value: 30, fade_ms: 0 };
+    assert!(matches!(client.call(&set), Err(DispError::Daemon(msg)) if msg == "no display named DP-9"));
+
+    //the wire format is the documented one, and nonsense is answered with an error
+    let mut raw = UnixStream::connect(&socket).unwrap();
+    let mut lines = BufReader::new(raw.try_clone().unwrap()).lines();
+    writeln!(raw, r#"{{"cmd":"increment","output":"eDP-1","prop":"brightness"}}"#).unwrap();
+    assert_eq!(lines.next().unwrap().unwrap(), r#"{"type":"ok"}"#);
+    writeln!(raw, r#"{{"cmd":"dim"}}"#).unwrap();
+    assert!(lines.next().unwrap().unwrap().starts_with(r#"{"type":"error","message":"bad request"#));
+
+    //commands work the same through the daemon
+    let parse = |args: &[&str]| Cli::try_parse_from(std::iter::once("tuib").chain(args.iter().copied())).unwrap();
+    match parse(&["dec", "-o", "HDMI-1", "--step", "10"]).command {
+        Some(Command::Dec(step)) => step.run(&mut client, false).unwrap(),
+        _ => unreachable!(),
+    }
+    match parse(&["get", "-p", "brightness"]).command {
+        Some(Command::Get(get)) => assert_eq!(get.run(&mut client).unwrap(), "31\n90"),
+        _ => unreachable!(),
+    }
+
+    //restore goes through the daemon too, so it doesn't fight with it over the displays
+    let state = r#"[displays.eDP-1]
+brightness = 45
+gamma = [100, 100, 100]
+"#;
+    std::fs::write(dir.path().join("state.toml"), state).unwrap();
+    client.call(&Request::Restore).unwrap();
+    assert_eq!(mock.output("eDP-1").unwrap().brightness, 45);
+
+    assert_eq!(client.request(&Request::Shutdown).unwrap(), Response::Ok);
+    thread.join().unwrap();
+}
+
+#[test]
+fn linked_mode_and_groups_stay_with_their_connection() {
+    let dir = TempDir::new().unwrap();
+    let (mock, socket, thread) = daemon(&dir);
+    let set = |value| Request::Set { output: String::from("eDP-1"), prop: DispProp::Brightness, value, fade_ms: 0 };
+
+    //a TUI turns on linked mode, its own changes go to every display
+    let mut tui = Client::connect(&socket).unwrap();
+    tui.call(&Request::Link { linked: true }).unwrap();
+    tui.call(&set(40)).unwrap();
+    assert_eq!(mock.output("HDMI-1").unwrap().brightness, 40);
+    assert!(tui.call(&Request::Group { name: Some(String::from("desk")) }).is_err());
+
+    //a plain tuib set next to it only changes the display it names
+    let mut cli = Client::connect(&socket).unwrap();
+    cli.call(&set(60)).unwrap();
+    assert_eq!(mock.output("eDP-1").unwrap().brightness, 60);
+    assert_eq!(mock.output("HDMI-1").unwrap().brightness, 40);
+
+    cli.call(&Request::Shutdown).unwrap();
+    thread.join().unwrap();
+}
+
+#[test]
+fn tui_clients_show_the_daemon_history() {
+    let dir = TempDir::new().unwrap();
+    let (_mock, socket, thread) = daemon(&dir);
+    let (io_tx, io_rx) = channel();
+    let app = Arc::new(FairMutex::new(App::with_config(io_tx.clone(), &Config::default())));
+    let app_bridge = Arc::clone(&app);
+    let path = socket.clone();
+    let bridge = std::thread::spawn(move || client::bridge(app_bridge, &path, io_rx).unwrap());
+    io_tx.send(IoEvent::Initialize).unwrap();
+
+    //a change from another client shows up too
+    let mut cli = Client::connect(&socket).unwrap();
+    cli.call(&Request::Increment { output: String::from("eDP-1"), prop: DispProp::Brightness }).unwrap();
+    io_tx.send(IoEvent::DeviceDecrement(1, DispProp::Brightness)).unwrap();
+    let started = Instant::now();
+    loop {
+        let done = app.lock().history().done().len();
+        if done == 2 {
+            break;
+        }
+        assert!(started.elapsed() < Duration::from_secs(5), "history never reached the TUI");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(app.lock().history(), &cli.history().unwrap());
+
+    io_tx.send(IoEvent::Shutdown).unwrap();
+    bridge.join().unwrap();
+    cli.call(&Request::Shutdown).unwrap();
+    thread.join().unwrap();
+}
+
+#[test]
+fn subscribers_see_changes() {
+    let dir = TempDir::new().unwrap();
+    let (_mock, socket, thread) = daemon(&dir);
+    let mut changes = Client::connect(&socket).unwrap().subscribe().unwrap();
+    let brightness = |displays: Vec<tuib::cli::Report>| displays.iter().map(|r| r.brightness).collect::<Vec<_>>();
+    assert_eq!(brightness(changes.next().unwrap()), [80, 100]);
+
+    let mut client = Client::connect(&socket).unwrap();
+    client.call(&Request::Scale { output: String::from("HDMI-1"), prop: DispProp::Brightness, scale: 5 }).unwrap();
+    assert_eq!(brightness(changes.next().unwrap()), [80, 50]);
+    //changes from clients go in the history too
+    client.call(&Request::Undo).unwrap();
+    assert_eq!(brightness(changes.next().unwrap()), [80, 100]);
+
+    client.call(&Request::Shutdown).unwrap();
+    thread.join().unwrap();
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use common::{Harness, NEUTRAL};
+use tuib::config::Config;
+use tuib::disp_mgr::backend::Mock;
+use tuib::night_light::{sun_times, NightLight, Phase};
+
+///2024-06-21 00:00 UTC
+const SOLSTICE: i64 = 1718928000;
+const HOUR: i64 = 3600;
+
+fn london() -> NightLight {
+    NightLight::new(london_config().night_light.unwrap())
+}
+
+fn london_config() -> Config {
+    Config::parse(
+        r#"
+        [night_light]
+        latitude = 51.5
+        longitude = -0.13
+        night_temperature = 3000
+        night_brightness = 60
+        "#,
+    )
+    .unwrap()
+}
+
+#[test]
+fn sunrise_and_sunset() {
+    //London on the solstice: sunrise 03:43, sunset 20:21 UTC
+    let n = ((SOLSTICE + 12 * HOUR) as f64 / 86400.0 + 2440587.5 - 2451545.0).round();
+    let (rise, set) = sun_times(n, 51.5, -0.13).unwrap();
+    assert!((rise - (SOLSTICE + 3 * HOUR + 43 * 60)).abs() < 5 * 60);
+    assert!((set - (SOLSTICE + 20 * HOUR + 21 * 60)).abs() < 5 * 60);
+
+    //the sun doesn't set north of the arctic circle in june, and doesn't rise in the south
+    assert_eq!(sun_times(n, 80.0, 0.0), Err(true));
+    assert_eq!(sun_times(n, -80.0, 0.0), Err(false));
+}
+
+#[test]
+fn phases_and_transitions() {
+    let nl = london();
+    let noon = nl.status(SOLSTICE + 12 * HOUR);
+    assert_eq!(noon.phase, Phase::Day);
+    assert_eq!((noon.target.kelvin, noon.target.brightness), (6500, Some(100)));
+    //next is when the sunset transition starts, half an hour before sunset
+    let next = noon.next.unwrap();
+    assert!((next - (SOLSTICE + 19 * HOUR + 51 * 60)).abs() < 5 * 60);
+
+    let sunset = nl.status(SOLSTICE + 20 * HOUR + 21 * 60);
+    assert_eq!(sunset.phase, Phase::Sunset);
+    assert!(sunset.target.kelvin > 3000 && sunset.target.kelvin < 6500);
+    assert!(sunset.next.unwrap() > next);
+
+    let night = nl.status(SOLSTICE + 23 * HOUR);
+    assert_eq!(night.phase, Phase::Night);
+    assert_eq!((night.target.kelvin, night.target.brightness), (3000, Some(60)));
+
+    assert_eq!(nl.status(SOLSTICE + 3 * HOUR + 43 * 60).phase, Phase::Sunrise);
+    assert_eq!(nl.status(SOLSTICE + 2 * HOUR).phase, Phase::Night);
+}
+
+#[test]
+fn polar_day_has_no_next_transition() {
+    let config = Config::parse("[night_light]\nlatitude = 80.0\nlongitude = 0.0").unwrap();
+    let status = NightLight::new(config.night_light.unwrap()).status(SOLSTICE);
+    assert_eq!(status.phase, Phase::Day);
+    assert_eq!(status.next, None);
+}
+
+#[test]
+fn app_sets_every_display_on_tick() {
+    let mock = Mock::new().with_output("eDP-1", 100, NEUTRAL).with_output("DP-1", 90, NEUTRAL);
+    let mut h = Harness::with_config(mock, &london_config());
+    h.app.lock().update_night_light(SOLSTICE + 23 * HOUR);
+    h.drain();
+    for disp in &h.handler.disp_mgr().disps {
+        assert_eq!((disp.temperature, disp.brightness), (3000, 60));
+    }
+    assert!(h.screen().contains("Night light: Night 3000K until"));
+
+    //nothing is sent again until the target changes, so keys aren't fought over
+    let applied = h.mock.applied().len();
+    h.app.lock().update_night_light(SOLSTICE + 23 * HOUR + 60);
+    h.drain();
+    assert_eq!(h.mock.applied().len(), applied);
+}
+
+#[test]
+fn client_of_a_daemon_only_shows_the_night_light() {
+    let mock = Mock::new().with_output("eDP-1", 100, NEUTRAL);
+    let mut h = Harness::with_config(mock, &london_config());
+    h.app.lock().set_drives_night_light(false);
+    h.app.lock().update_night_light(SOLSTICE + 23 * HOUR);
+    h.drain();
+    assert!(h.mock.applied().is_empty());
+    assert_eq!(h.handler.disp_mgr().disps[0].brightness, 100);
+    assert!(h.screen().contains("Night light: Night 3000K until"));
+}
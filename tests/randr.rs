@@ -0,0 +1,67 @@
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use tuib::disp_mgr::backend::{DisplayBackend, Randr};
+use tuib::disp_mgr::disp::{Disp, Gamma};
+
+///An Xvfb server on a display nothing else uses. Killed on drop
+struct Xvfb {
+    server: Child,
+    display: String,
+}
+
+impl Xvfb {
+    ///Start Xvfb and wait for its socket. None if it isn't installed
+    fn start() -> Option<Self> {
+        let n = (90..200).find(|n| {
+            !Path::new(&format!("/tmp/.X11-unix/X{}", n)).exists() && !Path::new(&format!("/tmp/.X{}-lock", n)).exists()
+        })?;
+        let display = format!(":{}", n);
+        let server = Command::new("Xvfb")
+            .args([display.as_str(), "-screen", "0", "1024x768x24", "-nolisten", "tcp"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let xvfb = Xvfb { server, display };
+        let started = Instant::now();
+        while !Path::new(&format!("/tmp/.X11-unix/X{}", n)).exists() {
+            assert!(started.elapsed() < Duration::from_secs(10), "Xvfb didn't start on {}", xvfb.display);
+            thread::sleep(Duration::from_millis(50));
+        }
+        Some(xvfb)
+    }
+}
+
+impl Drop for Xvfb {
+    fn drop(&mut self) {
+        let _ = self.server.kill();
+        let _ = self.server.wait();
+    }
+}
+
+#[test]
+#[ignore = "needs Xvfb, run with cargo test --test randr -- --ignored"]
+fn gamma_round_trips_through_xvfb() {
+    let xvfb = Xvfb::start().expect("Xvfb isn't installed");
+    let mut randr = Randr::connect(Some(&xvfb.display)).unwrap();
+    let outputs = randr.outputs().unwrap();
+    assert!(!outputs.is_empty(), "Xvfb has no randr outputs");
+    let output = &outputs[0];
+
+    for (brightness, gamma) in [(70, Gamma { r: 100, g: 90, b: 80 }), (100, Gamma { r: 100, g: 100, b: 100 })] {
+        randr.apply(&Disp::new(output.clone(), brightness, gamma)).unwrap();
+        let (read, read_gamma) = randr.read_state(output).unwrap();
+        //ramps are 16 bit, reading back can be a percent off
+        let close = |a: u8, b: u8| a.abs_diff(b) <= 1;
+        assert!(close(read, brightness), "{} read back as {}", brightness, read);
+        assert!(
+            close(read_gamma.r, gamma.r) && close(read_gamma.g, gamma.g) && close(read_gamma.b, gamma.b),
+            "{:?} read back as {:?}",
+            gamma,
+            read_gamma
+        );
+    }
+    assert!(randr.read_state("DP-9").is_err());
+}
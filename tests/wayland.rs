@@ -0,0 +1,82 @@
+#![cfg(feature = "wayland")]
+
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use tuib::disp_mgr::backend::{DisplayBackend, Wayland};
+use tuib::disp_mgr::disp::{Disp, Gamma};
+use tuib::disp_mgr::error::DispError;
+
+///A headless sway with one output, HEADLESS-1, running in its own runtime dir. Killed on drop
+struct Sway {
+    compositor: Child,
+    runtime: TempDir,
+}
+
+impl Sway {
+    ///Start sway and point $WAYLAND_DISPLAY at it. None if it isn't installed
+    fn start() -> Option<Self> {
+        let runtime = TempDir::new().unwrap();
+        let config = runtime.path().join("config");
+        std::fs::write(&config, "").unwrap();
+        let compositor = Command::new("sway")
+            .arg("--config")
+            .arg(&config)
+            .env("XDG_RUNTIME_DIR", runtime.path())
+            .env("WLR_BACKENDS", "headless")
+            .env("WLR_HEADLESS_OUTPUTS", "1")
+            .env("WLR_LIBINPUT_NO_DEVICES", "1")
+            .env("WLR_RENDERER", "pixman")
+            .env_remove("WAYLAND_DISPLAY")
+            .env_remove("DISPLAY")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let sway = Sway { compositor, runtime };
+        let socket = sway.runtime.path().join("wayland-1");
+        let started = Instant::now();
+        while !Path::new(&socket).exists() {
+            assert!(started.elapsed() < Duration::from_secs(10), "sway didn't start");
+            thread::sleep(Duration::from_millis(50));
+        }
+        std::env::set_var("XDG_RUNTIME_DIR", sway.runtime.path());
+        std::env::set_var("WAYLAND_DISPLAY", "wayland-1");
+        Some(sway)
+    }
+}
+
+impl Drop for Sway {
+    fn drop(&mut self) {
+        let _ = self.compositor.kill();
+        let _ = self.compositor.wait();
+    }
+}
+
+#[test]
+#[ignore = "needs sway, run with cargo test --test wayland -- --ignored"]
+fn gamma_control_under_headless_sway() {
+    let _sway = Sway::start().expect("sway isn't installed");
+
+    //names come from wl_output v4, which takes the second roundtrip
+    let mut first = Wayland::new().unwrap();
+    assert_eq!(first.outputs().unwrap(), ["HEADLESS-1"]);
+
+    //a table of the wrong size would be answered with failed
+    let disp = Disp::new(String::from("HEADLESS-1"), 70, Gamma { r: 100, g: 90, b: 80 });
+    first.apply(&disp).unwrap();
+    assert_eq!(first.read_state("HEADLESS-1").unwrap(), (70, disp.gamma));
+
+    //only one client gets to control an output's gamma, the next is sent failed
+    let mut second = Wayland::new().unwrap();
+    assert!(second.outputs().unwrap().is_empty());
+    assert!(matches!(second.apply(&disp), Err(DispError::Wayland(_))));
+
+    //the compositor lets go of it when the first client disconnects
+    drop(first);
+    let mut third = Wayland::new().unwrap();
+    assert_eq!(third.outputs().unwrap(), ["HEADLESS-1"]);
+    third.apply(&disp).unwrap();
+}